@@ -0,0 +1,81 @@
+//! Compares proof-of-work hash rates across thread counts.
+//!
+//! Run with `cargo bench --bench mining [-- <zeros> <blocks>]`, where
+//! `zeros` is the number of leading zero hex digits the target requires.
+//! Every thread count mines the same blocks and must find the same nonces.
+
+use std::env;
+use std::time::Instant;
+
+use chainforge::mining::{self, Solution};
+use chainforge::{Amount, Block, Target, Transaction};
+
+fn main() {
+    let mut args = env::args().skip(1).filter(|arg| arg != "--bench");
+    let zeros = args
+        .next()
+        .map_or(5, |arg| arg.parse().expect("leading zeros"));
+    let blocks = args
+        .next()
+        .map_or(4, |arg| arg.parse().expect("block count"));
+
+    let headers: Vec<_> = (0..blocks)
+        .map(|index| {
+            let coinbase = Transaction::coinbase("bench".to_string(), Amount::ZERO, index);
+            Block::new(index, vec![coinbase], "0".to_string()).header
+        })
+        .collect();
+
+    let mut thread_counts = vec![1];
+    while thread_counts.last().unwrap() * 2 <= mining::default_threads() {
+        thread_counts.push(thread_counts.last().unwrap() * 2);
+    }
+    if *thread_counts.last().unwrap() != mining::default_threads() {
+        thread_counts.push(mining::default_threads());
+    }
+
+    let target = Target::from_leading_zeros(zeros);
+    println!(
+        "target {} ({} leading zeros), {} blocks",
+        target, zeros, blocks
+    );
+    println!(
+        "{:>8} {:>14} {:>10} {:>14} {:>8}",
+        "threads", "hashes", "seconds", "hashes/s", "speedup"
+    );
+
+    let mut baseline: Option<(Vec<Solution>, f64)> = None;
+    for threads in thread_counts {
+        let started = Instant::now();
+        let solutions: Vec<Solution> = headers
+            .iter()
+            .map(|header| mining::search(header, target, threads).expect("solution"))
+            .collect();
+        let seconds = started.elapsed().as_secs_f64();
+        let hashes: u64 = solutions.iter().map(|solution| solution.hashes).sum();
+        let rate = hashes as f64 / seconds;
+
+        let speedup = match &baseline {
+            None => 1.0,
+            Some((expected, base_rate)) => {
+                let nonces = |solutions: &[Solution]| -> Vec<u64> {
+                    solutions.iter().map(|solution| solution.nonce).collect()
+                };
+                assert_eq!(
+                    nonces(expected),
+                    nonces(&solutions),
+                    "nonces differ at {} threads",
+                    threads
+                );
+                rate / base_rate
+            }
+        };
+        println!(
+            "{:>8} {:>14} {:>10.3} {:>14.0} {:>7.2}x",
+            threads, hashes, seconds, rate, speedup
+        );
+        if baseline.is_none() {
+            baseline = Some((solutions, rate));
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder};
+
+/// Number of decimal places an `Amount` can represent.
+pub const DECIMALS: u32 = 8;
+/// Smallest units in one whole coin.
+pub const COIN: u64 = 10u64.pow(DECIMALS);
+
+/// A non-negative quantity of coins stored as an integer count of the
+/// smallest unit (10^-8 of a coin).
+///
+/// Serializes as that integer, so hashes never depend on float formatting.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(transparent)]
+pub struct Amount(u64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    /// The result exceeds `Amount::MAX`.
+    Overflow,
+    /// The result would be negative.
+    Underflow,
+    /// The string is not a plain decimal number such as `12.5`.
+    InvalidFormat(String),
+    /// The string has more than `DECIMALS` fractional digits.
+    TooPrecise(String),
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Overflow => write!(f, "amount overflow"),
+            AmountError::Underflow => write!(f, "amount underflow"),
+            AmountError::InvalidFormat(input) => write!(f, "invalid amount {:?}", input),
+            AmountError::TooPrecise(input) => write!(
+                f,
+                "amount {:?} has more than {} decimal places",
+                input, DECIMALS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    pub const fn from_units(units: u64) -> Self {
+        Amount(units)
+    }
+
+    /// Whole coins; fails if the result does not fit.
+    pub fn from_coins(coins: u64) -> Result<Self, AmountError> {
+        coins
+            .checked_mul(COIN)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    pub const fn units(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0
+            .checked_add(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Overflow)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Result<Amount, AmountError> {
+        self.0
+            .checked_sub(other.0)
+            .map(Amount)
+            .ok_or(AmountError::Underflow)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    /// Sums `amounts`, failing on overflow.
+    pub fn checked_sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Result<Amount, AmountError> {
+        amounts
+            .into_iter()
+            .try_fold(Amount::ZERO, |total, amount| total.checked_add(amount))
+    }
+}
+
+impl fmt::Display for Amount {
+    /// Formats as a decimal number of coins without trailing zeros,
+    /// e.g. `100`, `0.5` or `12.00000001`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let whole = self.0 / COIN;
+        let fraction = self.0 % COIN;
+        if fraction == 0 {
+            return write!(f, "{}", whole);
+        }
+        let digits = format!("{:0width$}", fraction, width = DECIMALS as usize);
+        write!(f, "{}.{}", whole, digits.trim_end_matches('0'))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    /// Parses a decimal number of coins such as `50`, `0.25` or `1.00000001`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let invalid = || AmountError::InvalidFormat(input.to_string());
+        let (whole, fraction) = match input.split_once('.') {
+            Some((whole, fraction)) => (whole, fraction),
+            None => (input, ""),
+        };
+
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+            return Err(invalid());
+        }
+        if input.ends_with('.') {
+            return Err(invalid());
+        }
+        if fraction.len() > DECIMALS as usize {
+            return Err(AmountError::TooPrecise(input.to_string()));
+        }
+
+        let whole: u64 = whole.parse().map_err(|_| AmountError::Overflow)?;
+        let fraction_units = if fraction.is_empty() {
+            0
+        } else {
+            let padded = format!("{:0<width$}", fraction, width = DECIMALS as usize);
+            padded.parse::<u64>().map_err(|_| invalid())?
+        };
+
+        Amount::from_coins(whole)?.checked_add(Amount(fraction_units))
+    }
+}
+
+/// The unit count as a `u64`.
+impl Encode for Amount {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        encoder.put_u64(self.0);
+    }
+}
+
+impl Decode for Amount {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Amount(decoder.get_u64()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amount(input: &str) -> Amount {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn arithmetic_fails_instead_of_wrapping() {
+        let one = Amount::from_units(1);
+        assert_eq!(Amount::MAX.checked_add(one), Err(AmountError::Overflow));
+        assert_eq!(Amount::ZERO.checked_sub(one), Err(AmountError::Underflow));
+        assert_eq!(Amount::MAX.saturating_add(one), Amount::MAX);
+        assert_eq!(Amount::ZERO.saturating_sub(one), Amount::ZERO);
+        assert_eq!(
+            Amount::checked_sum([amount("1.5"), amount("2.25")]),
+            Ok(amount("3.75"))
+        );
+        assert_eq!(
+            Amount::checked_sum([Amount::MAX, one]),
+            Err(AmountError::Overflow)
+        );
+        assert_eq!(
+            Amount::from_coins(u64::MAX / COIN + 1),
+            Err(AmountError::Overflow)
+        );
+    }
+
+    #[test]
+    fn parses_and_formats_decimal_coins() {
+        for input in ["0", "50", "0.25", "1.00000001", "184467440737.09551615"] {
+            assert_eq!(amount(input).to_string(), input);
+        }
+        assert_eq!(amount("1.50").units(), 150_000_000);
+        assert_eq!(amount("184467440737.09551615"), Amount::MAX);
+
+        for input in ["", ".5", "5.", "-1", "1e3", "1.2.3", " 1", "+1"] {
+            assert_eq!(
+                input.parse::<Amount>(),
+                Err(AmountError::InvalidFormat(input.to_string()))
+            );
+        }
+        assert_eq!(
+            "0.000000001".parse::<Amount>(),
+            Err(AmountError::TooPrecise("0.000000001".to_string()))
+        );
+        for input in [
+            "184467440737.09551616",
+            "184467440738",
+            "18446744073709551616",
+        ] {
+            assert_eq!(input.parse::<Amount>(), Err(AmountError::Overflow));
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use crate::encoding::{self, Decode, DecodeError, Decoder, Encode, Encoder};
+use crate::merkle;
+use crate::target::{Target, U256};
+use crate::transaction::Transaction;
+
+/// Current `BlockHeader::version`.
+pub const BLOCK_VERSION: u32 = 4;
+
+/// The fixed-size part of a block. Only the header is hashed, so proof of
+/// work costs the same regardless of how many transactions a block holds;
+/// the transactions are committed through `merkle_root`, and the account
+/// state after the block through `state_root`. How a block is sealed
+/// depends on the chain's consensus engine: proof of work fills in `bits`
+/// and `nonce`, signing engines `signer` and `signature`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub index: u64,
+    /// Whole seconds only, since the canonical encoding holds no more.
+    #[serde(deserialize_with = "whole_seconds")]
+    pub timestamp: DateTime<Utc>,
+    pub previous_hash: String,
+    pub merkle_root: String,
+    pub state_root: String,
+    /// Hex public key of the validator that signed the block; empty under
+    /// proof of work.
+    pub signer: String,
+    /// Hex signature by `signer` over `signing_bytes()`; empty under proof
+    /// of work.
+    pub signature: String,
+    /// Proof-of-work target in compact form; see `target::Target`.
+    pub bits: u32,
+    pub nonce: u64,
+}
+
+/// Deserializes a timestamp, refusing fractions of a second so that a
+/// header read from JSON always says exactly what its hash commits to.
+fn whole_seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    let timestamp = DateTime::<Utc>::deserialize(deserializer)?;
+    if timestamp.timestamp_subsec_nanos() != 0 {
+        return Err(de::Error::custom("block timestamps are whole seconds"));
+    }
+    Ok(timestamp)
+}
+
+impl BlockHeader {
+    /// SHA-256 of the header's canonical encoding, as lowercase hex.
+    pub fn calculate_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.encode());
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// The encoding with `signature` left empty, which the signer signs.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        BlockHeader {
+            signature: String::new(),
+            ..self.clone()
+        }
+        .encode()
+    }
+
+    /// The proof-of-work target `bits` encodes, if it is a valid one.
+    pub fn target(&self) -> Option<Target> {
+        Target::from_bits(self.bits)
+    }
+
+    /// Work this header proves; zero if `bits` is invalid.
+    pub fn work(&self) -> U256 {
+        self.target().map_or(U256::ZERO, Target::work)
+    }
+}
+
+/// Total work proved by `headers`, the quantity fork choice maximizes.
+pub fn chain_work<H: AsRef<BlockHeader>>(headers: &[H]) -> U256 {
+    headers.iter().fold(U256::ZERO, |total, header| {
+        total.saturating_add(header.as_ref().work())
+    })
+}
+
+impl AsRef<BlockHeader> for BlockHeader {
+    fn as_ref(&self) -> &BlockHeader {
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub transactions: Vec<Transaction>,
+    pub hash: String,
+}
+
+impl Block {
+    pub fn new(index: u64, transactions: Vec<Transaction>, previous_hash: String) -> Self {
+        // Block time is committed with one-second precision.
+        let timestamp = Utc::now().trunc_subsecs(0);
+        let header = BlockHeader {
+            version: BLOCK_VERSION,
+            index,
+            timestamp,
+            previous_hash,
+            merkle_root: encoding::to_hex(&merkle::merkle_root(&transactions)),
+            state_root: encoding::to_hex(&merkle::EMPTY_ROOT),
+            signer: String::new(),
+            signature: String::new(),
+            bits: 0,
+            nonce: 0,
+        };
+        let hash = header.calculate_hash();
+        Block {
+            header,
+            transactions,
+            hash,
+        }
+    }
+
+    pub fn calculate_hash(&self) -> String {
+        self.header.calculate_hash()
+    }
+
+    /// Merkle root of the block's current transactions, as lowercase hex.
+    pub fn calculate_merkle_root(&self) -> String {
+        encoding::to_hex(&merkle::merkle_root(&self.transactions))
+    }
+}
+
+impl AsRef<BlockHeader> for Block {
+    fn as_ref(&self) -> &BlockHeader {
+        &self.header
+    }
+}
+
+/// `version`, `index`, `timestamp` (Unix seconds), `previous_hash`,
+/// `merkle_root`, `state_root`, `signer`, `signature`, `bits`, `nonce`.
+/// The nonce comes last so miners can hash everything before it once.
+impl Encode for BlockHeader {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        encoder.put_u32(self.version);
+        encoder.put_u64(self.index);
+        encoder.put_i64(self.timestamp.timestamp());
+        encoder.put_str(&self.previous_hash);
+        encoder.put_str(&self.merkle_root);
+        encoder.put_str(&self.state_root);
+        encoder.put_str(&self.signer);
+        encoder.put_str(&self.signature);
+        encoder.put_u32(self.bits);
+        encoder.put_u64(self.nonce);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(BlockHeader {
+            version: decoder.get_u32()?,
+            index: decoder.get_u64()?,
+            timestamp: DateTime::from_timestamp(decoder.get_i64()?, 0)
+                .ok_or(DecodeError::InvalidValue("timestamp"))?,
+            previous_hash: decoder.get_string()?,
+            merkle_root: decoder.get_string()?,
+            state_root: decoder.get_string()?,
+            signer: decoder.get_string()?,
+            signature: decoder.get_string()?,
+            bits: decoder.get_u32()?,
+            nonce: decoder.get_u64()?,
+        })
+    }
+}
+
+/// The header followed by the transactions. The stored `hash` is not
+/// encoded; it is recomputed on decode.
+impl Encode for Block {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        self.header.encode_to(encoder);
+        encoder.put_seq(&self.transactions);
+    }
+}
+
+impl Decode for Block {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let header = BlockHeader::decode_from(decoder)?;
+        let transactions = decoder.get_seq()?;
+        let hash = header.calculate_hash();
+        Ok(Block {
+            header,
+            transactions,
+            hash,
+        })
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Block #{}\nTimestamp: {}\nPrevious Hash: {}\nMerkle Root: {}\nState Root: {}\nHash: {}\nBits: {:08x}\nNonce: {}\nTransactions: {}",
+            self.header.index,
+            self.header.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            self.header.previous_hash,
+            self.header.merkle_root,
+            self.header.state_root,
+            self.hash,
+            self.header.bits,
+            self.header.nonce,
+            self.transactions.len()
+        )?;
+        if !self.header.signer.is_empty() {
+            write!(f, "\nSigner: {}", self.header.signer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::{Consensus, ProofOfAuthority};
+    use crate::crypto::KeyPair;
+    use crate::genesis::GenesisConfig;
+    use crate::validation::{self, ChainError};
+    use chrono::TimeZone;
+
+    fn coinbase() -> Transaction {
+        Transaction::coinbase("Miner1".into(), "100.001".parse().unwrap(), 1)
+    }
+
+    fn timestamp() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()
+    }
+
+    #[test]
+    fn block_matches_vector() {
+        let transfer = Transaction::with_fee(
+            "Alice".into(),
+            "Bob".into(),
+            "1.5".parse().unwrap(),
+            "0.001".parse().unwrap(),
+        );
+        assert_eq!(
+            encoding::to_hex(&merkle::leaf_hash(&coinbase())),
+            "067ccba1c6018eec520eebfc05bcbc586d4fe5602fab0d728b68cd481637f4b3"
+        );
+        let mut block = Block::new(1, vec![transfer, coinbase()], "00ab".into());
+        block.header.timestamp = timestamp();
+        block.header.bits = 0x2000ffff;
+        block.header.nonce = 42;
+        assert_eq!(
+            block.header.merkle_root,
+            "9176f6597723ba3d223eb88cbc0fdabd4d899f2e2874486011de8562e8f281d0"
+        );
+        let encoded = block.header.encode();
+        assert_eq!(
+            encoding::to_hex(&encoded),
+            concat!(
+                "0000000400000000000000010000000065937d25000000043030616200000040",
+                "3931373666363539373732336261336432323365623838636263306664616264",
+                "3464383939663265323837343438363031316465383536326538663238316430",
+                "0000004030303030303030303030303030303030303030303030303030303030",
+                "3030303030303030303030303030303030303030303030303030303030303030",
+                "3030303000000000000000002000ffff000000000000002a",
+            )
+        );
+        assert_eq!(
+            block.calculate_hash(),
+            "1a8b381c408d866716ee858b606b49a69453ead07e16f8d1737941c7fe9aac06"
+        );
+        assert_eq!(BlockHeader::decode(&encoded).unwrap(), block.header);
+    }
+
+    #[test]
+    fn signed_block_matches_vector() {
+        let key = KeyPair::from_secret_key(&[1u8; 32]);
+        let engine = ProofOfAuthority {
+            validators: vec![key.address()],
+            key: Some(key),
+        };
+        let mut block = Block::new(1, vec![coinbase()], "00ab".into());
+        block.header.timestamp = timestamp();
+        engine.seal(&mut block, &[]).unwrap();
+        assert_eq!(
+            encoding::to_hex(&block.header.signing_bytes()),
+            concat!(
+                "0000000400000000000000010000000065937d25000000043030616200000040",
+                "3036376363626131633630313865656335323065656266633035626362633538",
+                "3664346665353630326661623064373238623638636434383136333766346233",
+                "0000004030303030303030303030303030303030303030303030303030303030",
+                "3030303030303030303030303030303030303030303030303030303030303030",
+                "3030303000000040386138386533646437343039663139356664353264623264",
+                "3363626135643732636136373039626631643934313231626633373438383031",
+                "623430663666356300000000000000000000000000000000",
+            )
+        );
+        assert_eq!(
+            block.header.signature,
+            concat!(
+                "7707fc93dbb0e3d624916a7497ac53fc8bbe78f4f9697258c4c96880b610c532",
+                "d9649193a0b07e397e28e9e1fa5a9284455b3078eac54210a08f8a4aa6429d04",
+            )
+        );
+        assert_eq!(
+            block.hash,
+            "46ab911623499cbe27de46381f8cc9d20f935c71fff2130a620d54ba585cc9de"
+        );
+        assert!(engine.verify_seal(&block.header, &block.hash, &[]).is_ok());
+    }
+
+    #[test]
+    fn timestamps_are_whole_seconds() {
+        let mut block = Block::new(1, vec![coinbase()], "00ab".into());
+        block.header.timestamp = timestamp();
+        let whole = block.calculate_hash();
+        block.header.timestamp += chrono::Duration::milliseconds(900);
+        assert_eq!(block.calculate_hash(), whole);
+
+        let json = serde_json::to_string(&block.header).unwrap();
+        assert!(serde_json::from_str::<BlockHeader>(&json).is_err());
+        let errors =
+            validation::validate_header(&block.header, 1, Some("00ab"), &GenesisConfig::default());
+        assert!(matches!(
+            errors[..],
+            [ChainError::FractionalTimestamp { index: 1, .. }]
+        ));
+    }
+}
@@ -0,0 +1,735 @@
+use chrono::Duration;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::mpsc::Sender;
+
+use crate::amount::Amount;
+use crate::block::{self, Block, BlockHeader};
+use crate::consensus::Consensus;
+use crate::crypto::KeyPair;
+use crate::encoding;
+use crate::events::ChainEvent;
+use crate::fork::{BlockError, BlockTree};
+use crate::genesis::{GenesisConfig, LedgerMode};
+use crate::merkle::MerkleTree;
+use crate::mining;
+use crate::spv::{BalanceProof, InclusionProof};
+use crate::state::State;
+use crate::storage::{ChainStore, FileStore, MemoryStore, StorageError, TxLocation};
+use crate::target::{Target, U256};
+use crate::transaction::Transaction;
+use crate::utxo::{OutPoint, TxIn, TxOut, UtxoError, UtxoSet};
+use crate::validation::{self, ChainError, ChainValidator, TransactionError};
+
+/// A chain of blocks kept in the store `S`, plus the transactions waiting
+/// to be mined into it.
+#[derive(Debug)]
+pub struct Blockchain<S = MemoryStore> {
+    pub(crate) store: S,
+    /// Target new blocks are mined at, unless the genesis sets a retarget
+    /// rule. Never easier than the genesis target.
+    pub(crate) target: Target,
+    /// Threads `mine_pending_transactions` searches for a nonce with.
+    /// Defaults to one per available core.
+    pub mining_threads: usize,
+    /// Key `mine_pending_transactions` signs blocks with when the genesis
+    /// selects a signing consensus engine.
+    pub validator_key: Option<KeyPair>,
+    /// Most blocks a reorganization may disconnect. Blocks deeper than
+    /// this below the tip are final. `None` allows any depth.
+    pub max_reorg_depth: Option<u64>,
+    pub pending_transactions: Vec<Transaction>,
+    pub genesis: GenesisConfig,
+    /// Balances, nonces and unspent outputs as of the tip.
+    pub(crate) state: State,
+    /// Known blocks that are not on the main chain.
+    pub(crate) tree: BlockTree,
+    pub(crate) subscribers: Vec<Sender<ChainEvent>>,
+}
+
+impl Blockchain {
+    pub fn new() -> Self {
+        Self::with_genesis(GenesisConfig::default())
+    }
+
+    /// Creates an in-memory chain rooted at the genesis block described by
+    /// `genesis`. Mining starts at the genesis target.
+    pub fn with_genesis(genesis: GenesisConfig) -> Self {
+        let block = genesis.build_block();
+        let mut state = State::new(genesis.ledger);
+        state.apply_block(&block);
+        let mut store = MemoryStore::new();
+        store
+            .put_block(block)
+            .expect("an empty memory store accepts the genesis block");
+        Self::from_parts(store, genesis, state)
+    }
+}
+
+impl Blockchain<FileStore> {
+    /// Opens the chain persisted in the directory `path`, creating it with
+    /// the default genesis block if it does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::open_with_genesis(path, GenesisConfig::default())
+    }
+
+    /// Opens the chain persisted in `path`. Stored blocks are recovered
+    /// after any crash and fully re-validated against `genesis` as they
+    /// are read, each only once; every block mined afterwards is durably
+    /// appended before it is accepted.
+    ///
+    /// Pending transactions are not persisted.
+    pub fn open_with_genesis(
+        path: impl AsRef<Path>,
+        genesis: GenesisConfig,
+    ) -> Result<Self, StorageError> {
+        let mut validator = ChainValidator::new(&genesis);
+        let mut store = FileStore::open_with(path, |block| validator.push(block))?;
+        let state = Self::load_state(&mut store, &genesis, validator)?;
+        Ok(Self::from_parts(store, genesis, state))
+    }
+}
+
+impl<S: ChainStore> Blockchain<S> {
+    /// Builds a chain on `store`, putting the genesis block described by
+    /// `genesis` if the store is empty and validating whatever it holds
+    /// while replaying it into the state.
+    pub fn with_store(mut store: S, genesis: GenesisConfig) -> Result<Self, StorageError> {
+        let mut validator = ChainValidator::new(&genesis);
+        for height in 0..store.len() {
+            let block = store
+                .get_block(height)?
+                .ok_or(StorageError::NotFound { height })?;
+            validator.push(&block);
+        }
+        let state = Self::load_state(&mut store, &genesis, validator)?;
+        Ok(Self::from_parts(store, genesis, state))
+    }
+
+    /// Puts the genesis block into `store` if it is empty, then returns the
+    /// state `validator` replayed from the stored blocks, or the first rule
+    /// they break.
+    fn load_state(
+        store: &mut S,
+        genesis: &GenesisConfig,
+        mut validator: ChainValidator,
+    ) -> Result<State, StorageError> {
+        if store.is_empty() {
+            let block = genesis.build_block();
+            validator.push(&block);
+            store.put_block(block)?;
+        }
+        validator.into_state().map_err(StorageError::Invalid)
+    }
+
+    /// Builds a chain on `store` whose blocks leave the ledger in `state`.
+    pub(crate) fn from_parts(store: S, genesis: GenesisConfig, state: State) -> Self {
+        Blockchain {
+            store,
+            target: genesis.target,
+            mining_threads: mining::default_threads(),
+            validator_key: None,
+            max_reorg_depth: None,
+            pending_transactions: Vec::new(),
+            state,
+            tree: BlockTree::default(),
+            subscribers: Vec::new(),
+            genesis,
+        }
+    }
+
+    pub fn ledger_mode(&self) -> LedgerMode {
+        self.genesis.ledger
+    }
+
+    /// Balances, nonces and unspent outputs as of the tip.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Unspent outputs of the confirmed chain. Empty unless the chain uses
+    /// `LedgerMode::Utxo`.
+    pub fn utxos(&self) -> &UtxoSet {
+        self.state.utxos()
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Header of every block from genesis to the tip.
+    pub fn headers(&self) -> &[BlockHeader] {
+        self.store.headers()
+    }
+
+    /// Blocks at heights `range`, read from the store.
+    pub fn get_blocks(&self, range: Range<u64>) -> Result<Vec<Block>, StorageError> {
+        self.store.get_blocks(range)
+    }
+
+    /// Number of blocks, including genesis.
+    pub fn len(&self) -> u64 {
+        self.store.len()
+    }
+
+    /// Always false: a chain holds at least its genesis block.
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    pub fn get_block(&self, height: u64) -> Result<Option<Block>, StorageError> {
+        self.store.get_block(height)
+    }
+
+    pub fn get_block_by_hash(&self, hash: &str) -> Result<Option<Block>, StorageError> {
+        match self.store.get_height(hash) {
+            Some(height) => self.store.get_block(height),
+            None => Ok(None),
+        }
+    }
+
+    /// The block at `height`, which the store must hold.
+    pub(crate) fn read_block(&self, height: u64) -> Result<Block, StorageError> {
+        self.store
+            .get_block(height)?
+            .ok_or(StorageError::NotFound { height })
+    }
+
+    pub fn locate_transaction(&self, txid: &str) -> Option<TxLocation> {
+        self.store.get_tx_location(txid)
+    }
+
+    pub fn get_transaction(&self, txid: &str) -> Result<Option<Transaction>, StorageError> {
+        let Some(location) = self.locate_transaction(txid) else {
+            return Ok(None);
+        };
+        let mut block = self.read_block(location.height)?;
+        Ok(Some(block.transactions.swap_remove(location.position)))
+    }
+
+    pub fn get_latest_block(&self) -> Result<Block, StorageError> {
+        self.read_block(self.len() - 1)
+    }
+
+    /// Header of the tip block.
+    pub fn latest_header(&self) -> &BlockHeader {
+        self.headers()
+            .last()
+            .expect("a chain holds at least its genesis block")
+    }
+
+    /// Hash of the tip block.
+    pub fn tip_hash(&self) -> &str {
+        self.store
+            .tip_hash()
+            .expect("a chain holds at least its genesis block")
+    }
+
+    /// Queues `transaction` for the next block if it is well-formed and uses
+    /// the chain's ledger model. In the account model it must carry its
+    /// sender's next nonce and its sender must afford it after every
+    /// transaction already pending, unstaking no more than its unlockable stake
+    /// and slashing only a recent offence, not yet slashed, by a proposer with
+    /// slashable stake; in the UTXO model it may only spend confirmed outputs
+    /// that no pending transaction spends.
+    pub fn add_transaction(&mut self, transaction: Transaction) -> Result<(), TransactionError> {
+        validation::check_transaction(&transaction)?;
+        validation::check_model(&transaction, self.ledger_mode())?;
+        match self.ledger_mode() {
+            LedgerMode::Account => {
+                let from = &transaction.from;
+                validation::check_nonce(&transaction, self.next_nonce(from))?;
+                let balance = self.get_spendable_balance(from);
+                if transaction.is_unstake() {
+                    let stake = self.get_unlockable_stake(from);
+                    validation::check_unstake(&transaction, stake, balance)?;
+                } else {
+                    if let Some((offender, offence)) = transaction.offence() {
+                        let slashable = self.get_slashable_stake(&offender);
+                        let slashed = self.state.is_slashed(&offender, offence);
+                        validation::check_slash(&transaction, slashable, slashed, self.len())?;
+                    }
+                    validation::check_spend(&transaction, balance)?;
+                }
+            }
+            LedgerMode::Utxo => {
+                let pending = self.pending_spends();
+                for input in &transaction.inputs {
+                    if pending.contains(&input.previous_output) {
+                        let outpoint = input.previous_output.clone();
+                        return Err(TransactionError::Utxo(UtxoError::DoubleSpend(outpoint)));
+                    }
+                }
+                self.utxos()
+                    .check(&transaction)
+                    .map_err(TransactionError::Utxo)?;
+            }
+        }
+        self.pending_transactions.push(transaction);
+        Ok(())
+    }
+
+    /// Builds a UTXO transaction from `key_pair` paying each of `payments`
+    /// plus `fee`. It spends confirmed outputs that no pending transaction
+    /// spends and returns anything left over to the sender as a final
+    /// change output. The result still has to be queued with
+    /// `add_transaction`.
+    pub fn create_payment(
+        &self,
+        key_pair: &KeyPair,
+        payments: Vec<TxOut>,
+        fee: Amount,
+    ) -> Result<Transaction, UtxoError> {
+        let amounts = payments.iter().map(|payment| payment.amount);
+        let required = Amount::checked_sum(amounts.chain([fee])).map_err(UtxoError::Overflow)?;
+        let address = key_pair.address();
+        let (outpoints, total) = self
+            .utxos()
+            .select(&address, required, &self.pending_spends())?;
+
+        let mut outputs = payments;
+        let change = total.saturating_sub(required);
+        if !change.is_zero() {
+            outputs.push(TxOut {
+                address,
+                amount: change,
+            });
+        }
+        let inputs = outpoints
+            .into_iter()
+            .map(|previous_output| TxIn { previous_output })
+            .collect();
+        Ok(Transaction::utxo(key_pair, inputs, outputs, fee))
+    }
+
+    /// Outputs spent by pending transactions.
+    fn pending_spends(&self) -> HashSet<OutPoint> {
+        self.pending_transactions
+            .iter()
+            .flat_map(|transaction| &transaction.inputs)
+            .map(|input| input.previous_output.clone())
+            .collect()
+    }
+
+    /// Seals every pending transaction plus a coinbase paying
+    /// `mining_reward_address` into a new block with the chain's consensus
+    /// engine. The block is checked like any other before it is stored, and
+    /// pending transactions are kept if it cannot be sealed, is invalid or
+    /// cannot be persisted.
+    pub fn mine_pending_transactions(
+        &mut self,
+        mining_reward_address: String,
+    ) -> Result<(), BlockError> {
+        // The proposer is drawn from the stake locked before the block.
+        let engine = self.engine();
+        let reward =
+            validation::block_reward(self.genesis.mining_reward, &self.pending_transactions);
+        let height = self.store.len();
+        let reward_transaction = Transaction::coinbase(mining_reward_address, reward, height);
+        let mut transactions = self.pending_transactions.clone();
+        transactions.push(reward_transaction);
+
+        let mut block = Block::new(height, transactions, self.tip_hash().to_string());
+        // Blocks mined faster than once a second still move past the median.
+        if let Some(median) = validation::median_time_past(self.headers()) {
+            block.header.timestamp = block.header.timestamp.max(median + Duration::seconds(1));
+        }
+        self.state.apply_block(&block);
+        block.header.state_root = encoding::to_hex(&self.state.root());
+
+        let sealed = engine.seal(&mut block, self.headers());
+        if let Err(error) = sealed {
+            self.state.revert_block();
+            return Err(error.into());
+        }
+        let errors = validation::validate_block(
+            &block,
+            self.headers(),
+            Some(self.tip_hash()),
+            &self.genesis,
+        );
+        if let Some(error) = errors.into_iter().next() {
+            self.state.revert_block();
+            return Err(error.into());
+        }
+        if let Err(error) = self.store.put_block(block.clone()) {
+            self.state.revert_block();
+            return Err(error.into());
+        }
+        self.pending_transactions.clear();
+        self.emit(ChainEvent::BlockConnected(block));
+        Ok(())
+    }
+
+    /// Removes the tip block and reverts its effect on the state. The
+    /// genesis block is never removed. Pending transactions are left as
+    /// they are.
+    pub fn disconnect_tip(&mut self) -> Result<Option<Block>, StorageError> {
+        let block = self.pop_tip()?;
+        if let Some(block) = &block {
+            self.emit(ChainEvent::BlockDisconnected(block.clone()));
+        }
+        Ok(block)
+    }
+
+    /// `disconnect_tip` without notifying subscribers.
+    pub(crate) fn pop_tip(&mut self) -> Result<Option<Block>, StorageError> {
+        if self.store.len() <= 1 {
+            return Ok(None);
+        }
+        let block = self.store.pop_block()?;
+        if block.is_some() {
+            self.state.revert_block();
+        }
+        Ok(block)
+    }
+
+    /// Target new blocks are mined at without a retarget rule.
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    /// Sets the target new blocks are mined at without a retarget rule.
+    /// Targets easier than the genesis target are clamped to it, since no
+    /// block may carry them.
+    pub fn set_target(&mut self, target: Target) {
+        self.target = target.min(self.genesis.target);
+    }
+
+    /// Target the next mined block will carry: the retargeted target if the
+    /// genesis sets a retarget rule, otherwise `target`.
+    pub fn next_target(&self) -> Target {
+        self.genesis
+            .expected_target(self.headers())
+            .unwrap_or(self.target)
+    }
+
+    /// Total work proved by every block in the chain.
+    pub fn chain_work(&self) -> U256 {
+        block::chain_work(self.headers())
+    }
+
+    /// The nonce the next transaction from `address` must carry, counting
+    /// both confirmed and pending transactions.
+    pub fn next_nonce(&self, address: &str) -> u64 {
+        let pending = self
+            .pending_transactions
+            .iter()
+            .filter(|transaction| transaction.from == address)
+            .count() as u64;
+        self.state.nonce(address) + pending
+    }
+
+    /// Confirmed balance of `address` as of the next block, including
+    /// unbonding stake released by then: the sum of its unspent outputs in
+    /// the UTXO model.
+    pub fn get_balance(&self, address: &str) -> Amount {
+        self.state.spendable(address, self.len())
+    }
+
+    /// Confirmed balance of `address` adjusted by every pending
+    /// transaction. In the UTXO model, outputs spent by pending transactions
+    /// are left out and pending outputs are not yet counted.
+    pub fn get_spendable_balance(&self, address: &str) -> Amount {
+        match self.ledger_mode() {
+            LedgerMode::Account => net_balance(
+                self.get_balance(address),
+                &self.pending_transactions,
+                address,
+            ),
+            LedgerMode::Utxo => {
+                let pending = self.pending_spends();
+                self.utxos()
+                    .owned_by(address)
+                    .into_iter()
+                    .filter(|(outpoint, _)| !pending.contains(*outpoint))
+                    .fold(Amount::ZERO, |total, (_, output)| {
+                        total.saturating_add(output.amount)
+                    })
+            }
+        }
+    }
+
+    /// Confirmed stake of `address` less what pending transactions unstake,
+    /// or zero if a pending transaction already slashes it.
+    pub fn get_unlockable_stake(&self, address: &str) -> Amount {
+        let mut stake = self.state.stake(address);
+        for transaction in &self.pending_transactions {
+            if transaction.is_unstake() && transaction.from == address {
+                stake = stake.saturating_sub(transaction.amount);
+            }
+            if transaction.is_slash() && transaction.offender().as_deref() == Some(address) {
+                stake = Amount::ZERO;
+            }
+        }
+        stake
+    }
+
+    /// What a slash in the next block would burn from `address`: its stake
+    /// plus unbonding stake not yet released, including what pending
+    /// transactions unstake, or zero if a pending transaction already
+    /// slashes it.
+    pub fn get_slashable_stake(&self, address: &str) -> Amount {
+        let slashed = self.pending_transactions.iter().any(|transaction| {
+            transaction.is_slash() && transaction.offender().as_deref() == Some(address)
+        });
+        if slashed {
+            return Amount::ZERO;
+        }
+        self.state.slashable(address, self.len())
+    }
+
+    /// Builds a proof that `transaction` is in the chain, for a light client
+    /// holding only headers.
+    pub fn prove_transaction(&self, txid: &str) -> Result<Option<InclusionProof>, StorageError> {
+        let Some(TxLocation { height, position }) = self.locate_transaction(txid) else {
+            return Ok(None);
+        };
+        let block = self.read_block(height)?;
+        let merkle_proof = MerkleTree::from_transactions(&block.transactions).proof(position);
+        Ok(merkle_proof.map(|merkle_proof| InclusionProof {
+            height,
+            position,
+            merkle_proof,
+            headers: self.headers().to_vec(),
+        }))
+    }
+
+    /// Builds a proof of what `address` holds as of the tip, for a light
+    /// client holding only headers.
+    pub fn prove_balance(&self, address: &str) -> BalanceProof {
+        BalanceProof {
+            height: self.latest_header().index,
+            address: address.to_string(),
+            account: self.state.account(address).copied().unwrap_or_default(),
+            state_proof: self.state.prove(address),
+            headers: self.headers().to_vec(),
+        }
+    }
+
+    pub fn is_chain_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    /// Checks the stored chain, reading it one block at a time. A chain
+    /// that breaks a rule fails with `StorageError::Invalid`.
+    pub fn validate(&self) -> Result<(), StorageError> {
+        match self.validate_all()?.into_iter().next() {
+            Some(error) => Err(StorageError::Invalid(error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Every rule the stored chain breaks, reading it one block at a time.
+    pub fn validate_all(&self) -> Result<Vec<ChainError>, StorageError> {
+        let mut validator = ChainValidator::new(&self.genesis);
+        for height in 0..self.len() {
+            validator.push(&self.read_block(height)?);
+        }
+        Ok(validator.finish())
+    }
+
+    /// Prints every block, reading them from the store one at a time.
+    pub fn display_chain(&self) -> Result<(), StorageError> {
+        println!("\n=== BLOCKCHAIN ===");
+        for height in 0..self.len() {
+            let block = self.read_block(height)?;
+            println!("{}", block);
+            println!("Transactions:");
+            for transaction in &block.transactions {
+                if transaction.is_utxo() {
+                    println!(
+                        "  {} spends {} outputs, fee {}:",
+                        transaction.from,
+                        transaction.inputs.len(),
+                        transaction.fee
+                    );
+                    for output in &transaction.outputs {
+                        println!("    -> {}: {}", output.address, output.amount);
+                    }
+                } else {
+                    println!(
+                        "  {} -> {}: {}",
+                        transaction.from, transaction.to, transaction.amount
+                    );
+                }
+            }
+            println!("{}", "-".repeat(50));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `balance` plus what `address` receives in `transactions` minus what it
+/// spends there, floored at zero. Unstaked amounts are not counted until
+/// confirmed.
+fn net_balance(balance: Amount, transactions: &[Transaction], address: &str) -> Amount {
+    let mut received = u128::from(balance.units());
+    let mut spent: u128 = 0;
+
+    for transaction in transactions {
+        if transaction.from == address {
+            spent += u128::from(transaction.fee.units());
+            if !transaction.is_unstake() {
+                spent += u128::from(transaction.amount.units());
+            }
+        }
+        if transaction.to == address {
+            received += u128::from(transaction.amount.units());
+        }
+    }
+
+    let balance = received.saturating_sub(spent);
+    Amount::from_units(u64::try_from(balance).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{coins, funded_genesis};
+
+    #[test]
+    fn blocks_and_transactions_are_indexed_by_hash_and_txid() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let mut chain = Blockchain::with_genesis(funded_genesis(&alice));
+        let payment = Transaction::signed(&alice, "Bob".into(), coins(1), Amount::ZERO, 0);
+        let txid = payment.txid();
+        assert_eq!(txid.len(), 64);
+        assert_eq!(chain.locate_transaction(&txid), None);
+        chain.add_transaction(payment.clone()).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+
+        let location = TxLocation {
+            height: 1,
+            position: 0,
+        };
+        assert_eq!(chain.locate_transaction(&txid), Some(location));
+        assert_eq!(chain.get_transaction(&txid).unwrap(), Some(payment));
+        let tip = chain.get_latest_block().unwrap();
+        let coinbase = tip.transactions[1].txid();
+        assert_eq!(
+            chain.get_transaction(&coinbase).unwrap(),
+            Some(tip.transactions[1].clone())
+        );
+        let found = chain.get_block_by_hash(&tip.hash).unwrap().unwrap();
+        assert_eq!(found.header, tip.header);
+        assert!(chain.get_block_by_hash("00ff").unwrap().is_none());
+
+        // Removing the block forgets everything it added.
+        let mut store = chain.store().clone();
+        store.pop_block().unwrap();
+        assert_eq!(store.get_tx_location(&txid), None);
+        assert_eq!(store.get_tx_location(&coinbase), None);
+        assert_eq!(store.get_height(&tip.hash), None);
+    }
+
+    #[test]
+    fn chain_work_sums_the_work_of_every_block() {
+        let mut chain = Blockchain::new();
+        let genesis_work = chain.genesis.target.work();
+        assert_eq!(chain.chain_work(), genesis_work);
+
+        chain.set_target(Target::from_leading_zeros(3));
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        let harder = Target::from_leading_zeros(3).work();
+        assert_eq!(chain.latest_header().work(), harder);
+        assert_eq!(chain.chain_work(), genesis_work.saturating_add(harder));
+
+        // The target may not be set easier than the genesis target.
+        chain.set_target(Target::from_bits(0x207fffff).unwrap());
+        assert_eq!(chain.target(), chain.genesis.target);
+    }
+
+    #[test]
+    fn utxo_payments_return_change_and_refuse_double_spends() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let genesis = GenesisConfig {
+            ledger: LedgerMode::Utxo,
+            ..funded_genesis(&alice)
+        };
+        let mut chain = Blockchain::with_genesis(genesis);
+        let funding = OutPoint {
+            txid: chain.genesis.transactions[0].txid(),
+            index: 0,
+        };
+
+        let payment = TxOut {
+            address: "Bob".into(),
+            amount: coins(30),
+        };
+        let transaction = chain
+            .create_payment(&alice, vec![payment.clone()], coins(1))
+            .unwrap();
+        assert_eq!(
+            transaction.inputs,
+            vec![TxIn {
+                previous_output: funding.clone()
+            }]
+        );
+        assert_eq!(
+            transaction.outputs,
+            vec![
+                payment.clone(),
+                TxOut {
+                    address: alice.address(),
+                    amount: coins(69),
+                },
+            ]
+        );
+        chain.add_transaction(transaction).unwrap();
+
+        // The only output is now spent by a pending transaction.
+        let double_spend = Transaction::utxo(
+            &alice,
+            vec![TxIn {
+                previous_output: funding.clone(),
+            }],
+            vec![payment.clone()],
+            coins(70),
+        );
+        assert_eq!(
+            chain.add_transaction(double_spend),
+            Err(TransactionError::Utxo(UtxoError::DoubleSpend(funding)))
+        );
+        assert!(matches!(
+            chain.create_payment(&alice, vec![payment], Amount::ZERO),
+            Err(UtxoError::InsufficientFunds { .. })
+        ));
+
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        assert_eq!(chain.get_balance("Bob"), coins(30));
+        assert_eq!(chain.get_balance(&alice.address()), coins(69));
+        assert!(chain.is_chain_valid());
+    }
+
+    #[test]
+    fn mining_refuses_blocks_the_genesis_would_reject() {
+        let genesis = GenesisConfig {
+            target: Target::from_leading_zeros(4),
+            ..GenesisConfig::default()
+        };
+        let easiest = Target::from_bits(0x207fffff).unwrap();
+        let mut chain = Blockchain::with_genesis(genesis.clone());
+        chain.set_target(easiest);
+        assert_eq!(chain.target(), genesis.target);
+
+        chain.target = easiest;
+        let root = chain.state().root();
+        assert!(matches!(
+            chain.mine_pending_transactions("Miner".into()),
+            Err(BlockError::Invalid(ChainError::InsufficientWork {
+                index: 1,
+                ..
+            }))
+        ));
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain.state().root(), root);
+    }
+}
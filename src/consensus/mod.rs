@@ -0,0 +1,278 @@
+//! Pluggable consensus engines.
+//!
+//! A `Consensus` engine seals the blocks a node produces, verifies the seals
+//! of blocks it receives and weighs chains for fork choice. Which engine a
+//! chain runs is part of its genesis: `ConsensusConfig::ProofOfWork` mines
+//! against the genesis target, `ConsensusConfig::ProofOfAuthority` has a
+//! fixed set of validators sign blocks instead, costing no hashing at all,
+//! and `ConsensusConfig::ProofOfStake` has whoever locked stake take turns
+//! in proportion to it. `ConsensusConfig::Custom` plugs in any other
+//! implementation of the trait. Whatever a block's seal, its hash, height,
+//! links, transactions and state root are checked the same way.
+
+mod poa;
+mod pos;
+mod pow;
+
+pub use poa::ProofOfAuthority;
+pub use pos::{select_proposer, ProofOfStake};
+pub use pow::ProofOfWork;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::block::{Block, BlockHeader};
+use crate::chain::Blockchain;
+use crate::crypto::{self, KeyPair};
+use crate::genesis::GenesisConfig;
+use crate::mining;
+use crate::state::State;
+use crate::storage::ChainStore;
+use crate::target::U256;
+use crate::validation::ChainError;
+
+/// Block production, seal verification and fork choice for one chain.
+/// The trait is object safe, so a chain can run an engine defined outside
+/// this crate through `ConsensusConfig::Custom`.
+pub trait Consensus: fmt::Debug {
+    /// Seals `block`, whose predecessors from genesis are `previous`, so
+    /// that `verify_seal` accepts it, and updates its hash.
+    fn seal(&self, block: &mut Block, previous: &[BlockHeader]) -> Result<(), SealError>;
+
+    /// Checks the seal of `header`, whose hash is `hash`. `previous` holds
+    /// the headers before it from genesis, or is empty if they are not
+    /// known yet, in which case checks that depend on them are skipped.
+    fn verify_seal(
+        &self,
+        header: &BlockHeader,
+        hash: &str,
+        previous: &[BlockHeader],
+    ) -> Result<(), ChainError>;
+
+    /// Checks `header` against `state`, the state after its parent, for
+    /// engines whose seal depends on the ledger. Most do not.
+    fn verify_state(&self, _header: &BlockHeader, _state: &State) -> Result<(), ChainError> {
+        Ok(())
+    }
+
+    /// Checks that the signer of `header` could propose blocks given
+    /// `state`, the state at the main chain's tip. Blocks whose parent's
+    /// state is not at hand are held only if this passes, so engines whose
+    /// `verify_state` depends on the ledger can keep outsiders from filling
+    /// the block tree. Most engines check nothing here.
+    fn verify_signer(&self, _header: &BlockHeader, _state: &State) -> Result<(), ChainError> {
+        Ok(())
+    }
+
+    /// What `header` adds to the weight of its chain.
+    fn weight(&self, header: &BlockHeader) -> U256;
+
+    /// Total weight of `headers`. Fork choice keeps the heaviest chain,
+    /// and the current one on a tie.
+    fn chain_weight(&self, headers: &[BlockHeader]) -> U256 {
+        headers.iter().fold(U256::ZERO, |total, header| {
+            total.saturating_add(self.weight(header))
+        })
+    }
+}
+
+/// Which consensus engine a chain runs.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub enum ConsensusConfig {
+    /// Blocks are mined against the genesis target; see `ProofOfWork`.
+    #[default]
+    ProofOfWork,
+    /// Blocks are signed by one of `validators`, given as addresses; see
+    /// `ProofOfAuthority`.
+    ProofOfAuthority { validators: Vec<String> },
+    /// Blocks are signed by a proposer drawn by stake; see `ProofOfStake`.
+    ProofOfStake,
+    /// Blocks are sealed and verified by an engine defined elsewhere. It is
+    /// used as given, for both verifying and producing blocks. It cannot be
+    /// serialized.
+    #[serde(skip)]
+    Custom(Arc<dyn Consensus + Send + Sync>),
+}
+
+/// Custom engines are equal only if they are the same instance.
+impl PartialEq for ConsensusConfig {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ConsensusConfig::ProofOfWork, ConsensusConfig::ProofOfWork) => true,
+            (
+                ConsensusConfig::ProofOfAuthority { validators },
+                ConsensusConfig::ProofOfAuthority { validators: other },
+            ) => validators == other,
+            (ConsensusConfig::ProofOfStake, ConsensusConfig::ProofOfStake) => true,
+            (ConsensusConfig::Custom(engine), ConsensusConfig::Custom(other)) => {
+                Arc::ptr_eq(engine, other)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ConsensusConfig {}
+
+/// A `Consensus` engine of whichever kind the genesis selects.
+#[derive(Debug, Clone)]
+pub enum ConsensusEngine {
+    ProofOfWork(ProofOfWork),
+    ProofOfAuthority(ProofOfAuthority),
+    ProofOfStake(ProofOfStake),
+    Custom(Arc<dyn Consensus + Send + Sync>),
+}
+
+impl ConsensusEngine {
+    fn inner(&self) -> &dyn Consensus {
+        match self {
+            ConsensusEngine::ProofOfWork(engine) => engine,
+            ConsensusEngine::ProofOfAuthority(engine) => engine,
+            ConsensusEngine::ProofOfStake(engine) => engine,
+            ConsensusEngine::Custom(engine) => engine.as_ref(),
+        }
+    }
+}
+
+impl Consensus for ConsensusEngine {
+    fn seal(&self, block: &mut Block, previous: &[BlockHeader]) -> Result<(), SealError> {
+        self.inner().seal(block, previous)
+    }
+
+    fn verify_seal(
+        &self,
+        header: &BlockHeader,
+        hash: &str,
+        previous: &[BlockHeader],
+    ) -> Result<(), ChainError> {
+        self.inner().verify_seal(header, hash, previous)
+    }
+
+    fn verify_state(&self, header: &BlockHeader, state: &State) -> Result<(), ChainError> {
+        self.inner().verify_state(header, state)
+    }
+
+    fn verify_signer(&self, header: &BlockHeader, state: &State) -> Result<(), ChainError> {
+        self.inner().verify_signer(header, state)
+    }
+
+    fn weight(&self, header: &BlockHeader) -> U256 {
+        self.inner().weight(header)
+    }
+
+    fn chain_weight(&self, headers: &[BlockHeader]) -> U256 {
+        self.inner().chain_weight(headers)
+    }
+}
+
+/// Signs `block` with `key` and updates its hash.
+fn sign_block(block: &mut Block, key: &KeyPair) {
+    block.header.signer = key.public_key();
+    block.header.signature = key.sign(&block.header.signing_bytes());
+    block.hash = block.calculate_hash();
+}
+
+/// Checks the signature of a signed `header` and returns its signer's
+/// address, or `None` for the genesis header, which must be unsigned.
+fn verify_signature(header: &BlockHeader) -> Result<Option<String>, ChainError> {
+    let index = header.index;
+    if index == 0 {
+        if !header.signer.is_empty() || !header.signature.is_empty() {
+            return Err(ChainError::BadGenesis { field: "signer" });
+        }
+        return Ok(None);
+    }
+    let address = crypto::address_from_public_key_hex(&header.signer)
+        .map_err(|error| ChainError::BadSeal { index, error })?;
+    crypto::verify_signature(&header.signer, &header.signing_bytes(), &header.signature)
+        .map_err(|error| ChainError::BadSeal { index, error })?;
+    Ok(Some(address))
+}
+
+/// Why a block could not be sealed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SealError {
+    /// The engine signs blocks but has no key.
+    MissingKey,
+    /// The key's address is not in the validator set.
+    NotValidator(String),
+    /// No stake is locked, so no proposer can be selected.
+    NoStake,
+    /// The key's address is not the selected proposer, given here.
+    NotProposer(String),
+}
+
+impl fmt::Display for SealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SealError::MissingKey => write!(f, "no validator key to sign the block with"),
+            SealError::NotValidator(address) => write!(f, "{} is not a validator", address),
+            SealError::NoStake => write!(f, "no stake is locked to select a proposer from"),
+            SealError::NotProposer(proposer) => {
+                write!(f, "the selected proposer is {}", proposer)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
+
+impl GenesisConfig {
+    /// The engine that verifies this chain's blocks. It seals genesis
+    /// blocks, but signing engines have no key for any other.
+    pub fn engine(&self) -> ConsensusEngine {
+        match &self.consensus {
+            ConsensusConfig::ProofOfWork => ConsensusEngine::ProofOfWork(ProofOfWork {
+                limit: self.target,
+                retarget: self.retarget.clone(),
+                target: self.target,
+                threads: mining::default_threads(),
+            }),
+            ConsensusConfig::ProofOfAuthority { validators } => {
+                ConsensusEngine::ProofOfAuthority(ProofOfAuthority {
+                    validators: validators.clone(),
+                    key: None,
+                })
+            }
+            ConsensusConfig::ProofOfStake => ConsensusEngine::ProofOfStake(ProofOfStake {
+                key: None,
+                stakes: Vec::new(),
+            }),
+            ConsensusConfig::Custom(engine) => ConsensusEngine::Custom(engine.clone()),
+        }
+    }
+}
+
+impl<S: ChainStore> Blockchain<S> {
+    /// The engine `mine_pending_transactions` seals blocks with: the
+    /// genesis engine mining at `target` on `mining_threads` threads, or
+    /// signing with `validator_key`, drawing proposers from the stake
+    /// locked as of the tip. A custom engine is used as configured.
+    pub fn engine(&self) -> ConsensusEngine {
+        match self.genesis.engine() {
+            ConsensusEngine::ProofOfWork(engine) => ConsensusEngine::ProofOfWork(ProofOfWork {
+                target: self.target,
+                threads: self.mining_threads,
+                ..engine
+            }),
+            ConsensusEngine::ProofOfAuthority(engine) => {
+                ConsensusEngine::ProofOfAuthority(ProofOfAuthority {
+                    key: self.validator_key.clone(),
+                    ..engine
+                })
+            }
+            ConsensusEngine::ProofOfStake(_) => ConsensusEngine::ProofOfStake(ProofOfStake {
+                key: self.validator_key.clone(),
+                stakes: self.state.stakes(),
+            }),
+            custom @ ConsensusEngine::Custom(_) => custom,
+        }
+    }
+
+    /// Weight of the main chain under the chain's consensus engine: its
+    /// total work under proof of work.
+    pub fn chain_weight(&self) -> U256 {
+        self.genesis.engine().chain_weight(self.headers())
+    }
+}
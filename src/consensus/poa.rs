@@ -0,0 +1,152 @@
+use crate::block::{Block, BlockHeader};
+use crate::crypto::KeyPair;
+use crate::target::U256;
+use crate::validation::ChainError;
+
+use super::{sign_block, verify_signature, Consensus, SealError};
+
+/// Proof of authority: every block after genesis is signed by one of a
+/// fixed set of validators. Each block weighs the same, so the longest
+/// chain wins.
+#[derive(Debug, Clone)]
+pub struct ProofOfAuthority {
+    /// Addresses allowed to sign blocks.
+    pub validators: Vec<String>,
+    /// Key this node signs its blocks with.
+    pub key: Option<KeyPair>,
+}
+
+impl ProofOfAuthority {
+    pub fn is_validator(&self, address: &str) -> bool {
+        self.validators.iter().any(|validator| validator == address)
+    }
+}
+
+impl Consensus for ProofOfAuthority {
+    /// Signs `block` with `key`. The genesis block is left unsigned.
+    fn seal(&self, block: &mut Block, _previous: &[BlockHeader]) -> Result<(), SealError> {
+        if block.header.index == 0 {
+            block.hash = block.calculate_hash();
+            return Ok(());
+        }
+        let key = self.key.as_ref().ok_or(SealError::MissingKey)?;
+        if !self.is_validator(&key.address()) {
+            return Err(SealError::NotValidator(key.address()));
+        }
+        sign_block(block, key);
+        Ok(())
+    }
+
+    fn verify_seal(
+        &self,
+        header: &BlockHeader,
+        _hash: &str,
+        _previous: &[BlockHeader],
+    ) -> Result<(), ChainError> {
+        match verify_signature(header)? {
+            Some(address) if !self.is_validator(&address) => Err(ChainError::UnauthorizedSigner {
+                index: header.index,
+                address,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn weight(&self, _header: &BlockHeader) -> U256 {
+        U256::ONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::chain::Blockchain;
+    use crate::consensus::ConsensusConfig;
+    use crate::crypto::CryptoError;
+    use crate::fork::BlockError;
+    use crate::genesis::GenesisConfig;
+    use crate::transaction::Transaction;
+
+    fn block(index: u64) -> Block {
+        let coinbase = Transaction::coinbase("Miner".into(), Amount::ZERO, index);
+        Block::new(index, vec![coinbase], "00ab".into())
+    }
+
+    #[test]
+    fn only_validators_seal_and_verify() {
+        let validator = KeyPair::from_secret_key(&[1u8; 32]);
+        let outsider = KeyPair::from_secret_key(&[2u8; 32]);
+        let engine = |key: Option<&KeyPair>| ProofOfAuthority {
+            validators: vec![validator.address()],
+            key: key.cloned(),
+        };
+
+        let mut sealed = block(1);
+        assert_eq!(
+            engine(None).seal(&mut sealed, &[]),
+            Err(SealError::MissingKey)
+        );
+        assert_eq!(
+            engine(Some(&outsider)).seal(&mut sealed, &[]),
+            Err(SealError::NotValidator(outsider.address()))
+        );
+        engine(Some(&validator)).seal(&mut sealed, &[]).unwrap();
+        assert_eq!(sealed.hash, sealed.calculate_hash());
+        let verify = |header: &BlockHeader| engine(None).verify_seal(header, "", &[]);
+        assert_eq!(verify(&sealed.header), Ok(()));
+
+        // An outsider's valid signature, and a validator's forged one.
+        let mut foreign = block(1);
+        sign_block(&mut foreign, &outsider);
+        assert_eq!(
+            verify(&foreign.header),
+            Err(ChainError::UnauthorizedSigner {
+                index: 1,
+                address: outsider.address()
+            })
+        );
+        let mut forged = sealed.header.clone();
+        forged.previous_hash = "00cd".into();
+        assert_eq!(
+            verify(&forged),
+            Err(ChainError::BadSeal {
+                index: 1,
+                error: CryptoError::BadSignature
+            })
+        );
+
+        // The genesis block is never signed.
+        let mut genesis = block(0);
+        engine(Some(&validator)).seal(&mut genesis, &[]).unwrap();
+        assert!(genesis.header.signature.is_empty());
+        assert_eq!(verify(&genesis.header), Ok(()));
+        sign_block(&mut genesis, &validator);
+        assert_eq!(
+            verify(&genesis.header),
+            Err(ChainError::BadGenesis { field: "signer" })
+        );
+    }
+
+    #[test]
+    fn chains_under_authority_need_a_validator_key() {
+        let validator = KeyPair::from_secret_key(&[1u8; 32]);
+        let genesis = GenesisConfig {
+            consensus: ConsensusConfig::ProofOfAuthority {
+                validators: vec![validator.address()],
+            },
+            ..GenesisConfig::default()
+        };
+        let mut chain = Blockchain::with_genesis(genesis);
+        assert!(matches!(
+            chain.mine_pending_transactions("Miner".into()),
+            Err(BlockError::Seal(SealError::MissingKey))
+        ));
+
+        chain.validator_key = Some(validator.clone());
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        assert_eq!(chain.latest_header().signer, validator.public_key());
+        assert!(chain.is_chain_valid());
+    }
+}
@@ -0,0 +1,233 @@
+use sha2::{Digest, Sha256};
+
+use crate::amount::Amount;
+use crate::block::{Block, BlockHeader};
+use crate::crypto::{self, KeyPair};
+use crate::state::State;
+use crate::target::U256;
+use crate::validation::ChainError;
+
+use super::{sign_block, verify_signature, Consensus, SealError};
+
+/// Proof of stake: every block after genesis is signed by a proposer drawn
+/// from the stake locked as of its parent, each address weighted by its
+/// stake and the draw seeded by the parent's hash. Each block weighs the
+/// same, so the longest chain wins. A proposer caught signing two blocks at
+/// one height can be reported with a slash transaction, which burns its
+/// stake.
+#[derive(Debug, Clone)]
+pub struct ProofOfStake {
+    /// Key this node signs its blocks with.
+    pub key: Option<KeyPair>,
+    /// Locked stake by address, ordered by address, that the proposer of
+    /// the next sealed block is drawn from.
+    pub stakes: Vec<(String, Amount)>,
+}
+
+/// Draws the proposer of the block after `previous_hash` from `stakes`,
+/// ordered by address as `State::stakes` returns them. The first 16 bytes
+/// of `SHA-256(previous_hash)` pick a point in the total stake, and the
+/// address whose share covers it proposes. Returns `None` if no stake is
+/// locked.
+pub fn select_proposer<'a>(stakes: &'a [(String, Amount)], previous_hash: &str) -> Option<&'a str> {
+    let total: u128 = stakes
+        .iter()
+        .map(|(_, stake)| u128::from(stake.units()))
+        .sum();
+    if total == 0 {
+        return None;
+    }
+    let seed = Sha256::digest(previous_hash.as_bytes());
+    let seed = u128::from_be_bytes(seed[..16].try_into().expect("a digest holds 16 bytes"));
+    let mut point = seed % total;
+    for (address, stake) in stakes {
+        let stake = u128::from(stake.units());
+        if point < stake {
+            return Some(address);
+        }
+        point -= stake;
+    }
+    None
+}
+
+impl Consensus for ProofOfStake {
+    /// Signs `block` with `key` if it belongs to the selected proposer. The
+    /// genesis block is left unsigned.
+    fn seal(&self, block: &mut Block, _previous: &[BlockHeader]) -> Result<(), SealError> {
+        if block.header.index == 0 {
+            block.hash = block.calculate_hash();
+            return Ok(());
+        }
+        let key = self.key.as_ref().ok_or(SealError::MissingKey)?;
+        let proposer =
+            select_proposer(&self.stakes, &block.header.previous_hash).ok_or(SealError::NoStake)?;
+        if proposer != key.address() {
+            return Err(SealError::NotProposer(proposer.to_string()));
+        }
+        sign_block(block, key);
+        Ok(())
+    }
+
+    fn verify_seal(
+        &self,
+        header: &BlockHeader,
+        _hash: &str,
+        _previous: &[BlockHeader],
+    ) -> Result<(), ChainError> {
+        verify_signature(header).map(|_| ())
+    }
+
+    /// Checks that the block was signed by the proposer `state` selects.
+    /// A malformed signer is left to `verify_seal`.
+    fn verify_state(&self, header: &BlockHeader, state: &State) -> Result<(), ChainError> {
+        let Ok(found) = crypto::address_from_public_key_hex(&header.signer) else {
+            return Ok(());
+        };
+        let stakes = state.stakes();
+        let expected = select_proposer(&stakes, &header.previous_hash);
+        if expected != Some(found.as_str()) {
+            return Err(ChainError::WrongProposer {
+                index: header.index,
+                expected: expected.map(str::to_string),
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that the signer has stake `state` could still slash, so only
+    /// stakers can make a node hold their blocks. A malformed signer is
+    /// left to `verify_seal`.
+    fn verify_signer(&self, header: &BlockHeader, state: &State) -> Result<(), ChainError> {
+        let Ok(address) = crypto::address_from_public_key_hex(&header.signer) else {
+            return Ok(());
+        };
+        if state.slashable(&address, state.height()).is_zero() {
+            return Err(ChainError::UnauthorizedSigner {
+                index: header.index,
+                address,
+            });
+        }
+        Ok(())
+    }
+
+    fn weight(&self, _header: &BlockHeader) -> U256 {
+        U256::ONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Blockchain;
+    use crate::consensus::ConsensusConfig;
+    use crate::fork::BlockError;
+    use crate::genesis::GenesisConfig;
+    use crate::testing::{coins, funded_genesis};
+    use crate::transaction::{Transaction, GENESIS_ADDRESS};
+
+    #[test]
+    fn proposers_are_drawn_in_proportion_to_stake() {
+        assert_eq!(select_proposer(&[], "00ab"), None);
+        let unstaked = [("A".to_string(), Amount::ZERO)];
+        assert_eq!(select_proposer(&unstaked, "00ab"), None);
+
+        let stakes = [
+            ("A".to_string(), coins(1)),
+            ("B".to_string(), Amount::ZERO),
+            ("C".to_string(), coins(3)),
+        ];
+        let draws: Vec<&str> = (0..1000)
+            .map(|parent| select_proposer(&stakes, &format!("{:064x}", parent)).unwrap())
+            .collect();
+        let count = |address| draws.iter().filter(|drawn| **drawn == address).count();
+        assert_eq!(count("B"), 0);
+        assert_eq!(count("A") + count("C"), 1000);
+        assert!((650..850).contains(&count("C")), "{}", count("C"));
+        // The draw depends only on the stakes and the parent.
+        assert_eq!(
+            select_proposer(&stakes, "00ab"),
+            select_proposer(&stakes, "00ab")
+        );
+    }
+
+    #[test]
+    fn only_the_selected_proposer_may_extend_the_chain() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let bob = KeyPair::from_secret_key(&[2u8; 32]);
+        let mut genesis = GenesisConfig {
+            consensus: ConsensusConfig::ProofOfStake,
+            ..funded_genesis(&alice)
+        };
+        genesis.transactions.extend([
+            Transaction::new(GENESIS_ADDRESS.to_string(), bob.address(), coins(100)),
+            Transaction::stake(&alice, coins(50), Amount::ZERO, 0),
+            Transaction::stake(&bob, coins(50), Amount::ZERO, 0),
+        ]);
+        let mut chain = Blockchain::with_genesis(genesis);
+        let stakes = chain.state().stakes();
+        let parent = chain.tip_hash().to_string();
+        let proposer = select_proposer(&stakes, &parent).unwrap().to_string();
+        let (selected, other) = if proposer == alice.address() {
+            (alice, bob)
+        } else {
+            (bob, alice)
+        };
+
+        chain.validator_key = Some(other.clone());
+        assert!(matches!(
+            chain.mine_pending_transactions("Miner".into()),
+            Err(BlockError::Seal(SealError::NotProposer(expected))) if expected == proposer
+        ));
+
+        // A block the other staker signs anyway is refused.
+        let engine = ProofOfStake {
+            stakes: vec![(other.address(), coins(1))],
+            key: Some(other.clone()),
+        };
+        let coinbase = Transaction::coinbase(other.address(), coins(1), 1);
+        let mut block = Block::new(1, vec![coinbase], parent);
+        engine.seal(&mut block, &[]).unwrap();
+        assert!(matches!(
+            chain.add_block(block),
+            Err(BlockError::Invalid(ChainError::WrongProposer { index: 1, expected: Some(expected), found }))
+                if expected == proposer && found == other.address()
+        ));
+
+        chain.validator_key = Some(selected);
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        assert!(chain.is_chain_valid());
+    }
+
+    #[test]
+    fn blocks_from_signers_without_stake_are_not_held() {
+        let validator = KeyPair::from_secret_key(&[1u8; 32]);
+        let mut genesis = GenesisConfig {
+            consensus: ConsensusConfig::ProofOfStake,
+            ..funded_genesis(&validator)
+        };
+        let stake = Transaction::stake(&validator, coins(50), Amount::ZERO, 0);
+        genesis.transactions.push(stake);
+        let mut chain = Blockchain::with_genesis(genesis);
+        chain.validator_key = Some(validator);
+        chain.mine_pending_transactions("Validator".into()).unwrap();
+
+        let outsider = KeyPair::from_secret_key(&[2u8; 32]);
+        let engine = ProofOfStake {
+            stakes: vec![(outsider.address(), coins(1))],
+            key: Some(outsider.clone()),
+        };
+        for (height, parent) in [(2, chain.tip_hash().to_string()), (3, "00ff".to_string())] {
+            let coinbase = Transaction::coinbase(outsider.address(), coins(1), height);
+            let mut block = Block::new(height, vec![coinbase], parent);
+            engine.seal(&mut block, &[]).unwrap();
+            assert!(matches!(
+                chain.add_block(block),
+                Err(BlockError::Invalid(ChainError::UnauthorizedSigner { .. }))
+            ));
+        }
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain.block_tree().orphans().count(), 0);
+        assert_eq!(chain.block_tree().side_blocks().count(), 0);
+    }
+}
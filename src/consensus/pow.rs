@@ -0,0 +1,78 @@
+use crate::block::{Block, BlockHeader};
+use crate::retarget::RetargetConfig;
+use crate::target::{Target, U256};
+use crate::validation::ChainError;
+
+use super::{Consensus, SealError};
+
+/// Proof of work: a block's hash must meet the target in its `bits`, and
+/// the chain with the most cumulative work wins.
+#[derive(Debug, Clone)]
+pub struct ProofOfWork {
+    /// The genesis target, which no block may be easier than.
+    pub limit: Target,
+    /// If set, the target every block after genesis must carry.
+    pub retarget: Option<RetargetConfig>,
+    /// Target new blocks are mined at without a retarget rule.
+    pub target: Target,
+    pub threads: usize,
+}
+
+impl ProofOfWork {
+    /// Target the block after `previous` is mined at.
+    pub fn next_target<H: AsRef<BlockHeader>>(&self, previous: &[H]) -> Target {
+        if previous.is_empty() {
+            return self.limit;
+        }
+        self.retarget.as_ref().map_or(self.target, |retarget| {
+            retarget.next_target(previous, self.limit)
+        })
+    }
+}
+
+impl Consensus for ProofOfWork {
+    fn seal(&self, block: &mut Block, previous: &[BlockHeader]) -> Result<(), SealError> {
+        block.mine_block_with_threads(self.next_target(previous), self.threads);
+        Ok(())
+    }
+
+    fn verify_seal(
+        &self,
+        header: &BlockHeader,
+        hash: &str,
+        previous: &[BlockHeader],
+    ) -> Result<(), ChainError> {
+        let index = header.index;
+        if index == 0 && header.bits != self.limit.to_bits() {
+            return Err(ChainError::BadGenesis { field: "bits" });
+        }
+        let Some(target) = header.target() else {
+            return Err(ChainError::InvalidTarget {
+                index,
+                bits: header.bits,
+            });
+        };
+        let required = target.min(self.limit);
+        if !required.is_met_by_hex(hash) {
+            return Err(ChainError::InsufficientWork { index, required });
+        }
+        match &self.retarget {
+            Some(retarget) if !previous.is_empty() => {
+                let expected = retarget.next_target(previous, self.limit);
+                if header.bits != expected.to_bits() {
+                    return Err(ChainError::UnexpectedTarget {
+                        index,
+                        expected,
+                        found: header.bits,
+                    });
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn weight(&self, header: &BlockHeader) -> U256 {
+        header.work()
+    }
+}
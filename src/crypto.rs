@@ -0,0 +1,189 @@
+//! Ed25519 key pairs, address derivation and signature checks.
+//!
+//! An address is the first 20 bytes of `SHA-256(public key)` as lowercase
+//! hex, so it can only be spent from by whoever holds the matching secret
+//! key.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand_core::OsRng;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use crate::encoding::{from_hex, to_hex};
+
+/// Length in bytes of the public-key hash used as an address.
+pub const ADDRESS_BYTES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    /// The public key is not 32 bytes of lowercase hex or not a valid
+    /// Ed25519 point.
+    InvalidPublicKey,
+    /// The signature is not 64 bytes of lowercase hex.
+    MalformedSignature,
+    /// The signature does not match the message and key.
+    BadSignature,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::InvalidPublicKey => write!(f, "invalid public key"),
+            CryptoError::MalformedSignature => write!(f, "malformed signature"),
+            CryptoError::BadSignature => write!(f, "signature verification failed"),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+/// An Ed25519 signing key and the address it controls.
+#[derive(Clone)]
+pub struct KeyPair {
+    signing_key: SigningKey,
+}
+
+impl KeyPair {
+    /// Generates a fresh key pair from the operating system's RNG.
+    pub fn generate() -> Self {
+        KeyPair {
+            signing_key: SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    pub fn from_secret_key(secret_key: &[u8; 32]) -> Self {
+        KeyPair {
+            signing_key: SigningKey::from_bytes(secret_key),
+        }
+    }
+
+    pub fn secret_key(&self) -> [u8; 32] {
+        self.signing_key.to_bytes()
+    }
+
+    /// Hex-encoded 32-byte public key.
+    pub fn public_key(&self) -> String {
+        to_hex(self.signing_key.verifying_key().as_bytes())
+    }
+
+    pub fn address(&self) -> String {
+        address_from_public_key(self.signing_key.verifying_key().as_bytes())
+    }
+
+    /// Hex-encoded 64-byte signature over `message`.
+    pub fn sign(&self, message: &[u8]) -> String {
+        to_hex(&self.signing_key.sign(message).to_bytes())
+    }
+}
+
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the secret key.
+        f.debug_struct("KeyPair")
+            .field("address", &self.address())
+            .finish()
+    }
+}
+
+pub fn address_from_public_key(public_key: &[u8]) -> String {
+    let digest = Sha256::digest(public_key);
+    to_hex(&digest[..ADDRESS_BYTES])
+}
+
+/// Address for a hex-encoded public key, which must be a valid Ed25519
+/// key.
+pub fn address_from_public_key_hex(public_key: &str) -> Result<String, CryptoError> {
+    let verifying_key = parse_public_key(public_key)?;
+    Ok(address_from_public_key(verifying_key.as_bytes()))
+}
+
+fn parse_public_key(public_key: &str) -> Result<VerifyingKey, CryptoError> {
+    let key_bytes: [u8; 32] = from_hex(public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(CryptoError::InvalidPublicKey)?;
+    VerifyingKey::from_bytes(&key_bytes).map_err(|_| CryptoError::InvalidPublicKey)
+}
+
+/// Checks a hex signature over `message` against a hex public key.
+pub fn verify_signature(
+    public_key: &str,
+    message: &[u8],
+    signature: &str,
+) -> Result<(), CryptoError> {
+    let verifying_key = parse_public_key(public_key)?;
+
+    let signature_bytes: [u8; 64] = from_hex(signature)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(CryptoError::MalformedSignature)?;
+    let signature = Signature::from_bytes(&signature_bytes);
+
+    verifying_key
+        .verify_strict(message, &signature)
+        .map_err(|_| CryptoError::BadSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::DecodeError;
+
+    #[test]
+    fn signatures_verify_only_for_their_key_and_message() {
+        let key = KeyPair::from_secret_key(&[1u8; 32]);
+        let other = KeyPair::from_secret_key(&[2u8; 32]);
+        let signature = key.sign(b"message");
+        assert_eq!(key.public_key().len(), 64);
+        assert_eq!(key.address().len(), 2 * ADDRESS_BYTES);
+        assert_eq!(
+            address_from_public_key_hex(&key.public_key()),
+            Ok(key.address())
+        );
+
+        assert_eq!(
+            verify_signature(&key.public_key(), b"message", &signature),
+            Ok(())
+        );
+        assert_eq!(
+            verify_signature(&key.public_key(), b"massage", &signature),
+            Err(CryptoError::BadSignature)
+        );
+        assert_eq!(
+            verify_signature(&other.public_key(), b"message", &signature),
+            Err(CryptoError::BadSignature)
+        );
+        assert_eq!(
+            verify_signature(&key.public_key(), b"message", &signature[2..]),
+            Err(CryptoError::MalformedSignature)
+        );
+        assert_eq!(
+            verify_signature(&key.public_key(), b"message", &signature.to_uppercase()),
+            Err(CryptoError::MalformedSignature)
+        );
+
+        // Keys must be exactly 32 bytes of lowercase hex.
+        let public_key = key.public_key();
+        for bad in [
+            &public_key[2..],
+            &format!("{}00", public_key),
+            &public_key.to_uppercase(),
+        ] {
+            assert_eq!(
+                address_from_public_key_hex(bad),
+                Err(CryptoError::InvalidPublicKey)
+            );
+        }
+    }
+
+    #[test]
+    fn hex_has_one_spelling() {
+        let bytes = [0x00, 0x0f, 0xa0, 0xff];
+        assert_eq!(to_hex(&bytes), "000fa0ff");
+        assert_eq!(from_hex("000fa0ff"), Ok(bytes.to_vec()));
+        assert_eq!(from_hex(""), Ok(Vec::new()));
+        for bad in ["000FA0FF", "abc", "0x00", "zz", "+f"] {
+            assert_eq!(from_hex(bad), Err(DecodeError::InvalidValue("hex")));
+        }
+    }
+}
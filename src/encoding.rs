@@ -0,0 +1,197 @@
+//! Canonical binary encoding used for hashing and on the wire.
+//!
+//! The format is deliberately simple so other implementations can
+//! reproduce it byte-for-byte:
+//!
+//! - `u32`, `u64` and `i64` are fixed-width big-endian.
+//! - Strings are a `u32` byte length followed by their UTF-8 bytes.
+//! - Sequences are a `u32` element count followed by each element.
+//!
+//! Every field is fixed-width or length-prefixed, so no two distinct
+//! values share an encoding. See `docs/encoding.md` for the layout of
+//! each type and test vectors.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended before the value was complete.
+    UnexpectedEof,
+    /// Bytes remain after the value was decoded.
+    TrailingBytes(usize),
+    /// A string field is not valid UTF-8.
+    InvalidUtf8,
+    /// A field holds a value outside its allowed range.
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of input"),
+            DecodeError::TrailingBytes(count) => write!(f, "{} trailing bytes", count),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::InvalidValue(field) => write!(f, "invalid value for {}", field),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Lowercase hex, as used for hashes, keys and signatures.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parses hex written by `to_hex`. Upper-case digits are rejected, so every
+/// byte string has exactly one accepted spelling.
+pub fn from_hex(input: &str) -> Result<Vec<u8>, DecodeError> {
+    let is_digit = |byte: u8| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte);
+    input
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.len() == 2 && pair.bytes().all(is_digit))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or(DecodeError::InvalidValue("hex"))
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_i64(&mut self, value: i64) {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// Writes a `u32` length prefix followed by `value`.
+    pub fn put_bytes(&mut self, value: &[u8]) {
+        let len = u32::try_from(value.len()).expect("field longer than u32::MAX bytes");
+        self.put_u32(len);
+        self.bytes.extend_from_slice(value);
+    }
+
+    pub fn put_str(&mut self, value: &str) {
+        self.put_bytes(value.as_bytes());
+    }
+
+    /// Writes a `u32` element count followed by each element.
+    pub fn put_seq<T: Encode>(&mut self, items: &[T]) {
+        let len = u32::try_from(items.len()).expect("sequence longer than u32::MAX items");
+        self.put_u32(len);
+        for item in items {
+            item.encode_to(self);
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Decoder { bytes }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn get_u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_be_bytes(self.take_array()?))
+    }
+
+    pub fn get_u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_be_bytes(self.take_array()?))
+    }
+
+    pub fn get_i64(&mut self) -> Result<i64, DecodeError> {
+        Ok(i64::from_be_bytes(self.take_array()?))
+    }
+
+    pub fn get_bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = self.get_u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn get_string(&mut self) -> Result<String, DecodeError> {
+        let bytes = self.get_bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    pub fn get_seq<T: Decode>(&mut self) -> Result<Vec<T>, DecodeError> {
+        let len = self.get_u32()? as usize;
+        // Every element takes at least one byte, so cap the allocation by
+        // what is left rather than trusting the prefix.
+        let mut items = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            items.push(T::decode_from(self)?);
+        }
+        Ok(items)
+    }
+
+    /// Fails if any input is left over.
+    pub fn finish(self) -> Result<(), DecodeError> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            remaining => Err(DecodeError::TrailingBytes(remaining)),
+        }
+    }
+}
+
+pub trait Encode {
+    fn encode_to(&self, encoder: &mut Encoder);
+
+    fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.encode_to(&mut encoder);
+        encoder.finish()
+    }
+}
+
+pub trait Decode: Sized {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, DecodeError>;
+
+    /// Decodes a value that must span all of `bytes`.
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut decoder = Decoder::new(bytes);
+        let value = Self::decode_from(&mut decoder)?;
+        decoder.finish()?;
+        Ok(value)
+    }
+}
@@ -0,0 +1,113 @@
+//! Notifications of changes to the main chain.
+//!
+//! Each subscriber receives every event, in order, on its own channel:
+//! `BlockConnected` when a block joins the tip of the main chain and
+//! `BlockDisconnected` when one leaves it. A reorganization sends the
+//! disconnections from the old tip down, then the connections from the
+//! fork point up, then a `Reorg` summarizing them. A reorganization that
+//! fails and restores the old chain sends nothing. Subscribers whose
+//! receiver has been dropped are forgotten.
+
+use std::sync::mpsc::{self, Receiver};
+
+use crate::block::Block;
+use crate::chain::Blockchain;
+use crate::storage::ChainStore;
+
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// The block became the tip of the main chain.
+    BlockConnected(Block),
+    /// The block was removed from the tip of the main chain.
+    BlockDisconnected(Block),
+    /// The main chain switched branches: the `depth` blocks ending at
+    /// `old_tip` were replaced by the branch ending at `new_tip`.
+    Reorg {
+        old_tip: String,
+        new_tip: String,
+        depth: u64,
+    },
+}
+
+impl<S: ChainStore> Blockchain<S> {
+    /// Returns a receiver for every event from now on.
+    pub fn subscribe(&mut self) -> Receiver<ChainEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    pub(crate) fn emit(&mut self, event: ChainEvent) {
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fork::BlockStatus;
+
+    /// The hash of each connected or disconnected block, tagged with
+    /// whether it was connected.
+    fn moves(events: impl IntoIterator<Item = ChainEvent>) -> Vec<(bool, String)> {
+        events
+            .into_iter()
+            .filter_map(|event| match event {
+                ChainEvent::BlockConnected(block) => Some((true, block.hash)),
+                ChainEvent::BlockDisconnected(block) => Some((false, block.hash)),
+                ChainEvent::Reorg { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn subscribers_see_every_change_to_the_main_chain_in_order() {
+        let mut chain = Blockchain::new();
+        let events = chain.subscribe();
+        drop(chain.subscribe());
+
+        chain.mine_pending_transactions("A".into()).unwrap();
+        let mined = chain.tip_hash().to_string();
+        assert_eq!(moves(events.try_iter()), [(true, mined.clone())]);
+        assert_eq!(chain.subscribers.len(), 1);
+        chain.disconnect_tip().unwrap();
+        assert_eq!(moves(events.try_iter()), [(false, mined)]);
+
+        chain.mine_pending_transactions("A".into()).unwrap();
+        let mined = chain.tip_hash().to_string();
+        events.try_iter().for_each(drop);
+
+        let mut rival = Blockchain::new();
+        rival.mine_pending_transactions("B".into()).unwrap();
+        rival.mine_pending_transactions("B".into()).unwrap();
+        let blocks = rival.get_blocks(1..3).unwrap();
+        let hashes: Vec<String> = blocks.iter().map(|block| block.hash.clone()).collect();
+        for block in blocks {
+            chain.add_block(block).unwrap();
+        }
+        let mut received: Vec<ChainEvent> = events.try_iter().collect();
+        assert!(matches!(
+            received.pop(),
+            Some(ChainEvent::Reorg { old_tip, new_tip, depth: 1 })
+                if old_tip == mined && new_tip == hashes[1]
+        ));
+        assert_eq!(
+            moves(received),
+            [
+                (false, mined),
+                (true, hashes[0].clone()),
+                (true, hashes[1].clone())
+            ]
+        );
+
+        // A block on the new tip is connected on its own.
+        rival.mine_pending_transactions("B".into()).unwrap();
+        let tip = rival.get_latest_block().unwrap();
+        assert_eq!(
+            chain.add_block(tip.clone()).unwrap(),
+            BlockStatus::Connected
+        );
+        assert_eq!(moves(events.try_iter()), [(true, tip.hash)]);
+    }
+}
@@ -0,0 +1,654 @@
+//! Competing blocks and heaviest-chain selection.
+//!
+//! The store holds only the main chain. Any other block with a valid seal
+//! is kept in the chain's `BlockTree`: on a side chain if its parent is
+//! known, or as an orphan until its parent arrives. The branch the consensus
+//! engine weighs heaviest, the one with the most cumulative work under proof
+//! of work, is the main chain, so when a side chain overtakes it the chain
+//! reorganizes. The side chain's blocks, proposers included, are first
+//! checked against the ledger in memory; a block that fails is dropped with
+//! its descendants and the store is never touched, so a cheap invalid side
+//! chain cannot force disconnects. Otherwise blocks above the fork point
+//! are disconnected, the side chain's blocks are connected in order, and
+//! the transactions only the old branch confirmed go back to
+//! `pending_transactions` if they are still valid. Ties keep the current
+//! main chain.
+//!
+//! A node with a `validator_key` that sees two blocks at one height signed
+//! by the same key queues a slash transaction reporting them.
+//!
+//! Under proof of stake, a block is held only if its signer has stake at
+//! the tip, so nodes without stake cannot fill the block tree.
+//!
+//! Side chains and orphans live in memory only, and the tree is bounded:
+//! past `MAX_SIDE_BLOCKS` the lowest side-chain tip is dropped, past
+//! `MAX_ORPHANS` the oldest orphan is, and orphans more than
+//! `MAX_ORPHAN_DISTANCE` blocks above the tip are refused.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::mem;
+
+use crate::amount::Amount;
+use crate::block::{Block, BlockHeader};
+use crate::chain::Blockchain;
+use crate::consensus::{Consensus, SealError};
+use crate::events::ChainEvent;
+use crate::genesis::GenesisConfig;
+use crate::storage::{ChainStore, StorageError};
+use crate::transaction::Transaction;
+use crate::validation::{self, ChainError};
+
+/// What `Blockchain::add_block` did with a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockStatus {
+    /// The block extended the main chain.
+    Connected,
+    /// The block is on a side chain no heavier than the main chain.
+    SideChain,
+    /// The block's parent is unknown; it is held until the parent arrives.
+    Orphan,
+    /// The block is already known.
+    Duplicate,
+    /// The block, or orphans waiting for it, made a side chain heavier
+    /// than the main chain, which it replaced: `disconnected` blocks left
+    /// the main chain and `connected` blocks joined it.
+    Reorganized { disconnected: u64, connected: u64 },
+}
+
+#[derive(Debug)]
+pub enum BlockError {
+    /// The block, or a side-chain block it depends on, breaks a consensus
+    /// rule.
+    Invalid(ChainError),
+    /// The block's branch leaves the main chain at `fork_height`, below
+    /// `finalized_height`, so adopting it would disconnect final blocks.
+    Finalized {
+        fork_height: u64,
+        finalized_height: u64,
+    },
+    /// The block's parent is unknown and, at `index`, it is more than
+    /// `MAX_ORPHAN_DISTANCE` blocks above the tip; orphans may reach
+    /// `limit`.
+    TooFarAhead {
+        index: u64,
+        limit: u64,
+    },
+    /// A block produced locally could not be sealed.
+    Seal(SealError),
+    Storage(StorageError),
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::Invalid(error) => write!(f, "block rejected: {}", error),
+            BlockError::Finalized {
+                fork_height,
+                finalized_height,
+            } => write!(
+                f,
+                "block forks from height {} below finalized height {}",
+                fork_height, finalized_height
+            ),
+            BlockError::TooFarAhead { index, limit } => write!(
+                f,
+                "orphan block #{} is above the highest orphan height {}",
+                index, limit
+            ),
+            BlockError::Seal(error) => write!(f, "cannot seal block: {}", error),
+            BlockError::Storage(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for BlockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BlockError::Invalid(error) => Some(error),
+            BlockError::Seal(error) => Some(error),
+            BlockError::Storage(error) => Some(error),
+            BlockError::Finalized { .. } | BlockError::TooFarAhead { .. } => None,
+        }
+    }
+}
+
+impl From<ChainError> for BlockError {
+    fn from(error: ChainError) -> Self {
+        BlockError::Invalid(error)
+    }
+}
+
+impl From<SealError> for BlockError {
+    fn from(error: SealError) -> Self {
+        BlockError::Seal(error)
+    }
+}
+
+impl From<StorageError> for BlockError {
+    fn from(error: StorageError) -> Self {
+        BlockError::Storage(error)
+    }
+}
+
+/// Most side-chain blocks a `BlockTree` holds. Past this, the lowest
+/// side-chain tip is dropped for each new block.
+pub const MAX_SIDE_BLOCKS: usize = 1024;
+/// Most orphans a `BlockTree` holds. Past this, the orphan held longest is
+/// dropped for each new one.
+pub const MAX_ORPHANS: usize = 256;
+/// Furthest above the tip an orphan may be.
+pub const MAX_ORPHAN_DISTANCE: u64 = 64;
+
+/// Blocks a chain knows about that are not on its main chain, at most
+/// `MAX_SIDE_BLOCKS` on side chains and `MAX_ORPHANS` orphans.
+#[derive(Debug, Clone, Default)]
+pub struct BlockTree {
+    /// Side-chain blocks by hash.
+    side: HashMap<String, Block>,
+    /// Blocks whose parent is unknown, oldest first.
+    orphans: Vec<Block>,
+}
+
+impl BlockTree {
+    /// Blocks on side chains, in no particular order.
+    pub fn side_blocks(&self) -> impl Iterator<Item = &Block> {
+        self.side.values()
+    }
+
+    /// Blocks whose parent is unknown, oldest first.
+    pub fn orphans(&self) -> impl Iterator<Item = &Block> {
+        self.orphans.iter()
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.side.contains_key(hash) || self.orphans().any(|block| block.hash == hash)
+    }
+
+    /// Adds a side-chain block, first making room by dropping the lowest
+    /// side-chain tip other than its parent.
+    fn insert_side(&mut self, block: Block) {
+        let parent = &block.header.previous_hash;
+        while self.side.len() >= MAX_SIDE_BLOCKS {
+            let parents: HashSet<&str> = self
+                .side
+                .values()
+                .map(|block| block.header.previous_hash.as_str())
+                .collect();
+            let Some(lowest) = self
+                .side
+                .values()
+                .filter(|block| !parents.contains(block.hash.as_str()) && block.hash != *parent)
+                .min_by_key(|block| block.header.index)
+                .map(|block| block.hash.clone())
+            else {
+                break;
+            };
+            self.side.remove(&lowest);
+        }
+        self.side.insert(block.hash.clone(), block);
+    }
+
+    /// Holds `block` until its parent arrives, dropping the oldest orphan
+    /// if `MAX_ORPHANS` are already held.
+    fn insert_orphan(&mut self, block: Block) {
+        if self.orphans.len() >= MAX_ORPHANS {
+            self.orphans.remove(0);
+        }
+        self.orphans.push(block);
+    }
+
+    /// Removes and returns the orphans waiting for `parent`.
+    fn take_orphans(&mut self, parent: &str) -> Vec<Block> {
+        let (children, rest) = mem::take(&mut self.orphans)
+            .into_iter()
+            .partition(|block| block.header.previous_hash == parent);
+        self.orphans = rest;
+        children
+    }
+
+    /// Removes the block `hash` and every block descending from it.
+    fn remove_branch(&mut self, hash: &str) {
+        let mut removed = vec![hash.to_string()];
+        while let Some(hash) = removed.pop() {
+            self.side.remove(&hash);
+            let orphans = self.take_orphans(&hash);
+            removed.extend(orphans.into_iter().map(|block| block.hash));
+            removed.extend(
+                self.side
+                    .values()
+                    .filter(|block| block.header.previous_hash == hash)
+                    .map(|block| block.hash.clone()),
+            );
+        }
+    }
+}
+
+impl<S: ChainStore> Blockchain<S> {
+    /// Known blocks that are not on the main chain.
+    pub fn block_tree(&self) -> &BlockTree {
+        &self.tree
+    }
+
+    /// Height at and below which blocks are final: `max_reorg_depth` below
+    /// the tip, or 0 without a limit.
+    pub fn finalized_height(&self) -> u64 {
+        let tip = self.store.len() - 1;
+        self.max_reorg_depth
+            .map_or(0, |depth| tip.saturating_sub(depth))
+    }
+
+    /// Accepts a block produced elsewhere. It extends the main chain, joins
+    /// a side chain or waits as an orphan; if its chain is now heavier than
+    /// the main chain, the chain reorganizes onto it. Orphans waiting for
+    /// the block are added after it.
+    ///
+    /// A block is rejected if its header is invalid where it attaches, if
+    /// its branch forks below `finalized_height`, or, once it would join the
+    /// main chain, if its transactions or state root are invalid there.
+    pub fn add_block(&mut self, block: Block) -> Result<BlockStatus, BlockError> {
+        let old_tip = self.tip_hash().to_string();
+        let hash = block.hash.clone();
+        let status = self.accept_block(block)?;
+        if status == BlockStatus::Orphan || status == BlockStatus::Duplicate {
+            return Ok(status);
+        }
+        self.adopt_orphans(&hash)?;
+        Ok(self.reorganized_from(&old_tip).unwrap_or(status))
+    }
+
+    /// Adds one block without looking at orphans. A block that starts a
+    /// reorganization is reported as `SideChain`.
+    fn accept_block(&mut self, block: Block) -> Result<BlockStatus, BlockError> {
+        check_proof(&block, &self.genesis)?;
+        if self.store.get_height(&block.hash).is_some() || self.tree.contains(&block.hash) {
+            return Ok(BlockStatus::Duplicate);
+        }
+        // Proposers are checked against their parent's state only once it
+        // is at hand, so signers the tip's state rules out are refused
+        // before anything is held.
+        self.genesis
+            .engine()
+            .verify_signer(&block.header, &self.state)?;
+
+        let parent = block.header.previous_hash.clone();
+        let Some(previous) = self.branch_headers(&parent) else {
+            let limit = self.store.len() - 1 + MAX_ORPHAN_DISTANCE;
+            if block.header.index > limit {
+                return Err(BlockError::TooFarAhead {
+                    index: block.header.index,
+                    limit,
+                });
+            }
+            self.tree.insert_orphan(block);
+            return Ok(BlockStatus::Orphan);
+        };
+        let errors = validation::validate_block(&block, &previous, Some(&parent), &self.genesis);
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error.into());
+        }
+        self.report_double_sign(&block.header);
+
+        if parent == self.tip_hash() {
+            self.connect_block(block.clone())?;
+            self.readmit_pending(Vec::new());
+            self.emit(ChainEvent::BlockConnected(block));
+            return Ok(BlockStatus::Connected);
+        }
+        self.check_finality(&parent)?;
+        let hash = block.hash.clone();
+        let engine = self.genesis.engine();
+        let weight = engine
+            .chain_weight(&previous)
+            .saturating_add(engine.weight(&block.header));
+        self.tree.insert_side(block);
+        if weight > self.chain_weight() {
+            self.reorganize(&hash)?;
+        }
+        Ok(BlockStatus::SideChain)
+    }
+
+    /// Queues a slash transaction from `validator_key` if another known
+    /// block at the height of `header`, with different signed contents,
+    /// was signed by the same key; see `validation::check_evidence`. Without
+    /// a validator key, or if the signer has nothing left to slash, does
+    /// nothing.
+    fn report_double_sign(&mut self, header: &BlockHeader) {
+        let Some(key) = self.validator_key.clone() else {
+            return;
+        };
+        if header.signer.is_empty() {
+            return;
+        }
+        let main = self.store.get_header(header.index);
+        let side = self.tree.side.values().map(|block| &block.header);
+        let Some(other) = main
+            .into_iter()
+            .chain(side)
+            .find(|other| validation::check_evidence(&[(*other).clone(), header.clone()]).is_ok())
+            .cloned()
+        else {
+            return;
+        };
+        let nonce = self.next_nonce(&key.address());
+        let transaction = Transaction::slash(&key, other, header.clone(), Amount::ZERO, nonce);
+        let _ = self.add_transaction(transaction);
+    }
+
+    /// How the main chain changed since its tip was `old_tip`, or `None` if
+    /// `old_tip` is still on it.
+    fn reorganized_from(&self, old_tip: &str) -> Option<BlockStatus> {
+        let mut disconnected = 0;
+        let mut current = old_tip;
+        while let Some(block) = self.tree.side.get(current) {
+            disconnected += 1;
+            current = &block.header.previous_hash;
+        }
+        let fork_height = self.store.get_height(current)?;
+        (disconnected > 0).then(|| BlockStatus::Reorganized {
+            disconnected,
+            connected: self.store.len() - 1 - fork_height,
+        })
+    }
+
+    /// Refuses a branch through the known block `hash` if it leaves the
+    /// main chain below `finalized_height`.
+    fn check_finality(&self, hash: &str) -> Result<(), BlockError> {
+        let fork_height = self
+            .fork_height(hash)
+            .expect("side chains descend from the main chain");
+        let finalized_height = self.finalized_height();
+        if fork_height < finalized_height {
+            return Err(BlockError::Finalized {
+                fork_height,
+                finalized_height,
+            });
+        }
+        Ok(())
+    }
+
+    /// Height of the last main-chain block on the branch through the known
+    /// block `hash`.
+    fn fork_height(&self, hash: &str) -> Option<u64> {
+        let mut current = hash;
+        while let Some(block) = self.tree.side.get(current) {
+            current = &block.header.previous_hash;
+        }
+        self.store.get_height(current)
+    }
+
+    /// Headers from genesis to the known block `hash`, following side
+    /// chains back to the main chain, or `None` if `hash` is unknown.
+    fn branch_headers(&self, hash: &str) -> Option<Vec<BlockHeader>> {
+        let mut side = Vec::new();
+        let mut current = hash;
+        while let Some(block) = self.tree.side.get(current) {
+            side.push(block.header.clone());
+            current = &block.header.previous_hash;
+        }
+        let height = self.store.get_height(current)?;
+        let mut headers = self.headers()[..=height as usize].to_vec();
+        headers.extend(side.into_iter().rev());
+        Some(headers)
+    }
+
+    /// Checks `block` against the ledger at the tip and appends it.
+    fn connect_block(&mut self, block: Block) -> Result<(), BlockError> {
+        validation::connect_block(&block, &mut self.state, &self.genesis)?;
+        if let Err(error) = self.store.put_block(block) {
+            self.state.revert_block();
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    /// Makes the side chain ending at `tip` the main chain.
+    fn reorganize(&mut self, tip: &str) -> Result<(), BlockError> {
+        let mut branch = Vec::new();
+        let mut current = tip.to_string();
+        while let Some(block) = self.tree.side.get(&current) {
+            current = block.header.previous_hash.clone();
+            branch.push(block.clone());
+        }
+        branch.reverse();
+        let fork_height = self
+            .store
+            .get_height(&current)
+            .expect("side chains descend from the main chain");
+        self.check_branch(&branch, fork_height)?;
+
+        // Disconnected blocks join the tree first, so a failure part way
+        // through never loses them.
+        let mut disconnected = Vec::new();
+        while self.store.len() > fork_height + 1 {
+            let block = self
+                .pop_tip()?
+                .expect("blocks above the fork point are not genesis");
+            disconnected.push(block);
+        }
+        disconnected.reverse();
+        for block in &disconnected {
+            self.tree.side.insert(block.hash.clone(), block.clone());
+        }
+
+        for (connected, block) in branch.iter().enumerate() {
+            if let Err(error) = self.connect_block(block.clone()) {
+                if let BlockError::Invalid(_) = error {
+                    self.tree.remove_branch(&block.hash);
+                }
+                for _ in 0..connected {
+                    self.pop_tip()?;
+                }
+                for block in &disconnected {
+                    self.connect_block(block.clone())?;
+                    self.tree.side.remove(&block.hash);
+                }
+                return Err(error);
+            }
+        }
+        for block in &branch {
+            self.tree.side.remove(&block.hash);
+        }
+
+        let displaced = disconnected
+            .iter()
+            .flat_map(|block| &block.transactions)
+            .filter(|transaction| !transaction.is_coinbase())
+            .cloned()
+            .collect();
+        self.readmit_pending(displaced);
+
+        let old_tip = disconnected.last().map(|block| block.hash.clone());
+        for block in disconnected.iter().rev() {
+            self.emit(ChainEvent::BlockDisconnected(block.clone()));
+        }
+        for block in branch {
+            self.emit(ChainEvent::BlockConnected(block));
+        }
+        if let Some(old_tip) = old_tip {
+            self.emit(ChainEvent::Reorg {
+                old_tip,
+                new_tip: tip.to_string(),
+                depth: disconnected.len() as u64,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks `branch`, which leaves the main chain after `fork_height`,
+    /// against the ledger by rewinding the state alone, then restores it.
+    /// An invalid block is dropped from the tree with its descendants.
+    fn check_branch(&mut self, branch: &[Block], fork_height: u64) -> Result<(), BlockError> {
+        let main = self.get_blocks(fork_height + 1..self.len())?;
+        for _ in &main {
+            self.state.revert_block();
+        }
+        let mut result = Ok(());
+        let mut connected = 0;
+        for block in branch {
+            if let Err(error) = validation::connect_block(block, &mut self.state, &self.genesis) {
+                self.tree.remove_branch(&block.hash);
+                result = Err(error.into());
+                break;
+            }
+            connected += 1;
+        }
+        for _ in 0..connected {
+            self.state.revert_block();
+        }
+        for block in &main {
+            self.state.apply_block(block);
+        }
+        result
+    }
+
+    /// Queues `displaced` ahead of the pending transactions again, dropping
+    /// any that are no longer valid at the tip, such as those it confirms.
+    fn readmit_pending(&mut self, displaced: Vec<Transaction>) {
+        let pending = mem::take(&mut self.pending_transactions);
+        for transaction in displaced.into_iter().chain(pending) {
+            let _ = self.add_transaction(transaction);
+        }
+    }
+
+    /// Adds the orphans waiting for `parent`. Invalid ones are dropped.
+    fn adopt_orphans(&mut self, parent: &str) -> Result<(), BlockError> {
+        for orphan in self.tree.take_orphans(parent) {
+            let hash = orphan.hash.clone();
+            match self.accept_block(orphan) {
+                Ok(_) => self.adopt_orphans(&hash)?,
+                Err(BlockError::Invalid(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The checks that need no other block: the stored hash and the seal. A
+/// block failing them is not worth keeping, even as an orphan.
+fn check_proof(block: &Block, genesis: &GenesisConfig) -> Result<(), ChainError> {
+    let index = block.header.index;
+    let computed = block.calculate_hash();
+    if block.hash != computed {
+        return Err(ChainError::HashMismatch {
+            index,
+            stored: block.hash.clone(),
+            computed,
+        });
+    }
+    validation::check_seal(&block.header, &block.hash, &[], genesis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::testing::{coins, funded_genesis};
+
+    fn mine(chain: &mut Blockchain, count: usize, miner: &str) {
+        for _ in 0..count {
+            chain.mine_pending_transactions(miner.to_string()).unwrap();
+        }
+    }
+
+    #[test]
+    fn reorg_returns_displaced_transactions_to_pending() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let genesis = funded_genesis(&alice);
+        let mut chain = Blockchain::with_genesis(genesis.clone());
+        let payment = Transaction::signed(&alice, "Bob".into(), coins(1), Amount::ZERO, 0);
+        chain.add_transaction(payment.clone()).unwrap();
+        mine(&mut chain, 1, "A");
+        assert_eq!(chain.get_balance("Bob"), coins(1));
+
+        let mut rival = Blockchain::with_genesis(genesis);
+        mine(&mut rival, 2, "B");
+        let mut blocks = rival.get_blocks(1..3).unwrap().into_iter();
+        let first = blocks.next().unwrap();
+        assert_eq!(chain.add_block(first).unwrap(), BlockStatus::SideChain);
+        assert_eq!(
+            chain.add_block(blocks.next().unwrap()).unwrap(),
+            BlockStatus::Reorganized {
+                disconnected: 1,
+                connected: 2,
+            }
+        );
+
+        assert_eq!(chain.tip_hash(), rival.tip_hash());
+        assert_eq!(chain.pending_transactions, vec![payment]);
+        assert_eq!(chain.get_balance("Bob"), Amount::ZERO);
+        assert_eq!(chain.block_tree().side_blocks().count(), 1);
+        assert!(chain.is_chain_valid());
+    }
+
+    #[test]
+    fn reorg_below_max_depth_is_refused() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let genesis = funded_genesis(&alice);
+        let mut chain = Blockchain::with_genesis(genesis.clone());
+        chain.max_reorg_depth = Some(1);
+        mine(&mut chain, 3, "A");
+        assert_eq!(chain.finalized_height(), 2);
+        let tip = chain.tip_hash().to_string();
+
+        let mut rival = Blockchain::with_genesis(genesis);
+        mine(&mut rival, 5, "B");
+        let mut blocks = rival.get_blocks(1..6).unwrap().into_iter();
+        assert!(matches!(
+            chain.add_block(blocks.next().unwrap()),
+            Err(BlockError::Finalized {
+                fork_height: 0,
+                finalized_height: 2,
+            })
+        ));
+        // The rest never find their refused parent.
+        for block in blocks {
+            assert_eq!(chain.add_block(block).unwrap(), BlockStatus::Orphan);
+        }
+        assert_eq!(chain.tip_hash(), tip);
+    }
+
+    #[test]
+    fn block_tree_drops_the_lowest_tip_and_the_oldest_orphan() {
+        let mut tree = BlockTree::default();
+        let blocks: Vec<Block> = (0..=MAX_SIDE_BLOCKS as u64)
+            .map(|index| Block::new(index + 1, Vec::new(), "00ab".into()))
+            .collect();
+        for block in &blocks[..MAX_SIDE_BLOCKS] {
+            tree.insert_side(block.clone());
+        }
+        // A child of the lowest tip keeps its parent.
+        let child = Block::new(2, Vec::new(), blocks[0].hash.clone());
+        tree.insert_side(child.clone());
+        assert_eq!(tree.side.len(), MAX_SIDE_BLOCKS);
+        assert!(tree.contains(&blocks[0].hash));
+        assert!(tree.contains(&child.hash));
+        assert!(!tree.contains(&blocks[1].hash));
+
+        for block in &blocks {
+            tree.insert_orphan(block.clone());
+        }
+        assert_eq!(tree.orphans().count(), MAX_ORPHANS);
+        assert_eq!(
+            tree.orphans().next().unwrap().hash,
+            blocks[blocks.len() - MAX_ORPHANS].hash
+        );
+    }
+
+    #[test]
+    fn orphans_far_above_the_tip_are_refused() {
+        let mut chain = Blockchain::new();
+        let index = MAX_ORPHAN_DISTANCE + 1;
+        let coinbase = Transaction::coinbase("Miner".into(), coins(1), index);
+        let mut block = Block::new(index, vec![coinbase], "00ff".into());
+        block.mine_block_with_threads(chain.genesis.target, 1);
+        assert!(matches!(
+            chain.add_block(block.clone()),
+            Err(BlockError::TooFarAhead { limit, .. }) if limit == MAX_ORPHAN_DISTANCE
+        ));
+
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        assert_eq!(chain.add_block(block).unwrap(), BlockStatus::Orphan);
+    }
+}
@@ -0,0 +1,133 @@
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::amount::{Amount, COIN};
+use crate::block::Block;
+use crate::consensus::{Consensus, ConsensusConfig};
+use crate::encoding;
+use crate::merkle;
+use crate::retarget::RetargetConfig;
+use crate::state::State;
+use crate::target::Target;
+use crate::transaction::{Transaction, GENESIS_ADDRESS};
+
+/// The canonical definition of a chain's first block.
+///
+/// Every node that agrees on a `GenesisConfig` derives the same genesis
+/// block, and validation rejects any chain whose block 0 differs from it.
+/// `target` doubles as the easiest proof-of-work target any later block
+/// may use, `ledger` fixes the transaction model for the whole chain, and
+/// `retarget`, if set, makes the target of every later block follow from
+/// block times. `consensus` selects how blocks are sealed; `target` and
+/// `retarget` only apply under proof of work. `mining_reward` is the most a
+/// coinbase may mint on top of its block's fees.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisConfig {
+    pub timestamp: DateTime<Utc>,
+    pub transactions: Vec<Transaction>,
+    pub previous_hash: String,
+    pub target: Target,
+    pub ledger: LedgerMode,
+    pub retarget: Option<RetargetConfig>,
+    pub consensus: ConsensusConfig,
+    pub mining_reward: Amount,
+}
+
+/// How user transactions move value.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LedgerMode {
+    /// `from` pays `amount` to `to` out of an account balance.
+    #[default]
+    Account,
+    /// Transactions spend unspent outputs and create new ones; see the
+    /// `utxo` module.
+    Utxo,
+}
+
+impl fmt::Display for LedgerMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerMode::Account => write!(f, "account"),
+            LedgerMode::Utxo => write!(f, "UTXO"),
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Hex Merkle root of the genesis transactions.
+    pub fn merkle_root(&self) -> String {
+        encoding::to_hex(&merkle::merkle_root(&self.transactions))
+    }
+
+    /// Hex root of the account state the genesis transactions create.
+    pub fn state_root(&self) -> String {
+        let mut state = State::new(self.ledger);
+        for transaction in &self.transactions {
+            state.apply_transaction(transaction);
+        }
+        encoding::to_hex(&state.root())
+    }
+
+    /// Builds and seals the genesis block described by this config.
+    pub fn build_block(&self) -> Block {
+        let mut block = Block::new(0, self.transactions.clone(), self.previous_hash.clone());
+        block.header.timestamp = self.timestamp;
+        block.header.state_root = self.state_root();
+        self.engine()
+            .seal(&mut block, &[])
+            .expect("genesis blocks need no key");
+        block
+    }
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        GenesisConfig {
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            transactions: vec![Transaction::new(
+                GENESIS_ADDRESS.to_string(),
+                GENESIS_ADDRESS.to_string(),
+                Amount::ZERO,
+            )],
+            previous_hash: "0".to_string(),
+            target: Target::from_leading_zeros(2),
+            ledger: LedgerMode::Account,
+            retarget: None,
+            consensus: ConsensusConfig::ProofOfWork,
+            mining_reward: Amount::from_units(100 * COIN),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::Encode;
+
+    #[test]
+    fn default_genesis_matches_vector() {
+        let block = GenesisConfig::default().build_block();
+        assert_eq!(
+            block.header.merkle_root,
+            "72d5013e92cba7237b808566d165d83887e16ca6e677a1609965f03b9086d53c"
+        );
+        assert_eq!(
+            encoding::to_hex(&block.header.encode()),
+            concat!(
+                "0000000400000000000000000000000065920080000000013000000040373264",
+                "3530313365393263626137323337623830383536366431363564383338383765",
+                "3136636136653637376131363039393635663033623930383664353363000000",
+                "4065316639646238616133383330623462366637323631363330653736323832",
+                "6361633637326133653963323733323432643036636236326366626339376362",
+                "6100000000000000002000ffff000000000000026d",
+            )
+        );
+        assert_eq!(block.header.bits, 0x2000ffff);
+        assert_eq!(block.header.nonce, 621);
+        assert_eq!(
+            block.hash,
+            "009168db80fc8fbbe98f03aa0c286f5a98cf901cd4343503015be2f4ff7a61aa"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+pub mod amount;
+pub mod block;
+pub mod chain;
+pub mod consensus;
+pub mod crypto;
+pub mod encoding;
+pub mod events;
+pub mod fork;
+pub mod genesis;
+pub mod merkle;
+pub mod mining;
+pub mod retarget;
+pub mod smt;
+pub mod snapshot;
+pub mod spv;
+pub mod state;
+pub mod storage;
+pub mod target;
+#[cfg(test)]
+mod testing;
+pub mod transaction;
+pub mod utxo;
+pub mod validation;
+
+pub use amount::{Amount, AmountError};
+pub use block::{Block, BlockHeader};
+pub use chain::Blockchain;
+pub use consensus::{
+    select_proposer, Consensus, ConsensusConfig, ConsensusEngine, ProofOfAuthority, ProofOfStake,
+    ProofOfWork, SealError,
+};
+pub use crypto::KeyPair;
+pub use encoding::{Decode, DecodeError, Encode};
+pub use events::ChainEvent;
+pub use fork::{BlockError, BlockStatus, BlockTree};
+pub use genesis::{GenesisConfig, LedgerMode};
+pub use mining::{CancellationToken, Miner, MiningOutcome, MiningProgress};
+pub use retarget::RetargetConfig;
+pub use smt::{SmtProof, SparseMerkleTree};
+pub use snapshot::{ChainSnapshot, ImportError};
+pub use spv::{BalanceProof, InclusionProof, LightClient, SpvError};
+pub use state::{Account, State};
+pub use storage::{ChainStore, FileStore, MemoryStore, StorageError, TxLocation};
+pub use target::{Target, U256};
+pub use transaction::Transaction;
+pub use utxo::{OutPoint, TxIn, TxOut, UtxoDelta, UtxoError, UtxoSet};
+pub use validation::{ChainError, TransactionError};
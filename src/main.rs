@@ -1,252 +1,175 @@
-use sha2::{Digest, Sha256};
-use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
-use std::fmt;
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Transaction {
-    pub from: String,
-    pub to: String,
-    pub amount: f64,
+use std::env;
+use std::error::Error;
+
+use chainforge::{validation, Amount, Blockchain, ChainStore, KeyPair, LightClient, Transaction};
+
+fn coins(amount: &str) -> Amount {
+    amount.parse().expect("demo amounts are valid decimals")
 }
 
-impl Transaction {
-    pub fn new(from: String, to: String, amount: f64) -> Self {
-        Transaction { from, to, amount }
+fn submit<S: ChainStore>(blockchain: &mut Blockchain<S>, transaction: Transaction) {
+    let description = format!(
+        "{} -> {}: {}",
+        transaction.from, transaction.to, transaction.amount
+    );
+    match blockchain.add_transaction(transaction) {
+        Ok(()) => println!("📝 Queued {}", description),
+        Err(error) => println!("⛔ Rejected {} ({})", description, error),
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct Block {
-    pub index: u64,
-    pub timestamp: DateTime<Utc>,
-    pub transactions: Vec<Transaction>,
-    pub previous_hash: String,
-    pub hash: String,
-    pub nonce: u64,
+fn pay<S: ChainStore>(
+    blockchain: &mut Blockchain<S>,
+    from: &KeyPair,
+    to: String,
+    amount: Amount,
+) -> Transaction {
+    let nonce = blockchain.next_nonce(&from.address());
+    let transaction = Transaction::signed(from, to, amount, Amount::ZERO, nonce);
+    submit(blockchain, transaction.clone());
+    transaction
 }
 
-impl Block {
-    pub fn new(index: u64, transactions: Vec<Transaction>, previous_hash: String) -> Self {
-        let timestamp = Utc::now();
-        let mut block = Block {
-            index,
-            timestamp,
-            transactions,
-            previous_hash,
-            hash: String::new(),
-            nonce: 0,
-        };
-        block.hash = block.calculate_hash();
-        block
-    }
+fn mine<S: ChainStore>(
+    blockchain: &mut Blockchain<S>,
+    reward_address: String,
+) -> Result<(), Box<dyn Error>> {
+    blockchain.mine_pending_transactions(reward_address)?;
+    let header = blockchain.latest_header();
+    println!(
+        "⛏️  Mined block #{}: {}",
+        header.index,
+        blockchain.tip_hash()
+    );
+    Ok(())
+}
 
-    pub fn calculate_hash(&self) -> String {
-        let data = format!(
-            "{}{}{}{}{}",
-            self.index,
-            self.timestamp.timestamp(),
-            serde_json::to_string(&self.transactions).unwrap_or_default(),
-            self.previous_hash,
-            self.nonce
-        );
-        let mut hasher = Sha256::new();
-        hasher.update(data.as_bytes());
-        format!("{:x}", hasher.finalize())
-    }
+fn main() -> Result<(), Box<dyn Error>> {
+    println!("🚀 Starting Simple Blockchain in Rust");
 
-    pub fn mine_block(&mut self, difficulty: usize) {
-        let target = "0".repeat(difficulty);
-        println!("Mining block {} with difficulty {}...", self.index, difficulty);
-        
-        while &self.hash[..difficulty] != target {
-            self.nonce += 1;
-            self.hash = self.calculate_hash();
+    // Create blockchain, persisted to a data directory if one is given
+    match env::args().nth(1) {
+        Some(data_dir) => {
+            let blockchain = Blockchain::open(&data_dir)?;
+            println!("💾 Loaded {} blocks from {}", blockchain.len(), data_dir);
+            run(blockchain)
         }
-        
-        println!("Block mined! Hash: {}", self.hash);
+        None => run(Blockchain::new()),
     }
 }
 
-impl fmt::Display for Block {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "Block #{}\nTimestamp: {}\nPrevious Hash: {}\nHash: {}\nNonce: {}\nTransactions: {}",
-            self.index,
-            self.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
-            self.previous_hash,
-            self.hash,
-            self.nonce,
-            self.transactions.len()
-        )
-    }
-}
+fn run<S: ChainStore>(mut blockchain: Blockchain<S>) -> Result<(), Box<dyn Error>> {
+    // Every participant owns a key pair; addresses are derived from public keys
+    let alice = KeyPair::generate();
+    let bob = KeyPair::generate();
+    let charlie = KeyPair::generate();
+    println!("\n🔑 Alice: {}", alice.address());
+    println!("🔑 Bob: {}", bob.address());
+    println!("🔑 Charlie: {}", charlie.address());
 
-#[derive(Debug)]
-pub struct Blockchain {
-    pub chain: Vec<Block>,
-    pub difficulty: usize,
-    pub pending_transactions: Vec<Transaction>,
-    pub mining_reward: f64,
-}
-
-impl Blockchain {
-    pub fn new() -> Self {
-        let mut blockchain = Blockchain {
-            chain: Vec::new(),
-            difficulty: 2,
-            pending_transactions: Vec::new(),
-            mining_reward: 100.0,
-        };
-        blockchain.create_genesis_block();
-        blockchain
-    }
+    // Fund Alice with a block reward so she has something to spend
+    println!("\n📦 Mining funding block for Alice...");
+    mine(&mut blockchain, alice.address())?;
 
-    fn create_genesis_block(&mut self) {
-        let genesis_transactions = vec![Transaction::new(
-            "Genesis".to_string(),
-            "Genesis".to_string(),
-            0.0,
-        )];
-        
-        let mut genesis_block = Block::new(0, genesis_transactions, "0".to_string());
-        genesis_block.mine_block(self.difficulty);
-        self.chain.push(genesis_block);
-    }
-
-    pub fn get_latest_block(&self) -> &Block {
-        self.chain.last().unwrap()
-    }
+    // Add transactions
+    let first_payment = pay(&mut blockchain, &alice, bob.address(), coins("50"));
 
-    pub fn add_transaction(&mut self, transaction: Transaction) {
-        self.pending_transactions.push(transaction);
-    }
+    pay(&mut blockchain, &bob, charlie.address(), coins("25"));
 
-    pub fn mine_pending_transactions(&mut self, mining_reward_address: String) {
-        let reward_transaction = Transaction::new(
-            "System".to_string(),
-            mining_reward_address,
-            self.mining_reward,
-        );
-        self.pending_transactions.push(reward_transaction);
-
-        let mut block = Block::new(
-            self.chain.len() as u64,
-            self.pending_transactions.clone(),
-            self.get_latest_block().hash.clone(),
-        );
-
-        block.mine_block(self.difficulty);
-        self.chain.push(block);
-        self.pending_transactions.clear();
-    }
+    // Mine block
+    println!("\n📦 Mining block with pending transactions...");
+    mine(&mut blockchain, "Miner1".to_string())?;
 
-    pub fn get_balance(&self, address: &str) -> f64 {
-        let mut balance = 0.0;
-
-        for block in &self.chain {
-            for transaction in &block.transactions {
-                if transaction.from == address {
-                    balance -= transaction.amount;
-                }
-                if transaction.to == address {
-                    balance += transaction.amount;
-                }
-            }
-        }
+    // Add more transactions
+    pay(&mut blockchain, &charlie, alice.address(), coins("10"));
 
-        balance
-    }
+    pay(&mut blockchain, &alice, bob.address(), coins("5"));
 
-    pub fn is_chain_valid(&self) -> bool {
-        for i in 1..self.chain.len() {
-            let current_block = &self.chain[i];
-            let previous_block = &self.chain[i - 1];
+    // These are refused before they ever reach a block
+    pay(&mut blockchain, &charlie, bob.address(), coins("1000"));
 
-            if current_block.hash != current_block.calculate_hash() {
-                return false;
-            }
+    submit(
+        &mut blockchain,
+        Transaction::new("System".to_string(), charlie.address(), coins("1000")),
+    );
 
-            if current_block.previous_hash != previous_block.hash {
-                return false;
-            }
-        }
-        true
-    }
+    // Replaying an already confirmed transfer is refused by its nonce
+    submit(&mut blockchain, first_payment);
 
-    pub fn display_chain(&self) {
-        println!("\n=== BLOCKCHAIN ===");
-        for block in &self.chain {
-            println!("{}", block);
-            println!("Transactions:");
-            for transaction in &block.transactions {
-                println!("  {} -> {}: {}", transaction.from, transaction.to, transaction.amount);
-            }
-            println!("{}", "-".repeat(50));
-        }
-    }
-}
+    // Bob cannot spend Alice's coins by signing with his own key
+    let mut forged = Transaction::new(alice.address(), bob.address(), coins("20"));
+    forged.sign(&bob);
+    submit(&mut blockchain, forged);
 
-fn main() {
-    println!("🚀 Starting Simple Blockchain in Rust");
-    
-    // Create blockchain
-    let mut blockchain = Blockchain::new();
-    
-    // Add transactions
-    blockchain.add_transaction(Transaction::new(
-        "Alice".to_string(),
-        "Bob".to_string(),
-        50.0,
-    ));
-    
-    blockchain.add_transaction(Transaction::new(
-        "Bob".to_string(),
-        "Charlie".to_string(),
-        25.0,
-    ));
-    
-    // Mine block
-    println!("\n📦 Mining block with pending transactions...");
-    blockchain.mine_pending_transactions("Miner1".to_string());
-    
-    // Add more transactions
-    blockchain.add_transaction(Transaction::new(
-        "Charlie".to_string(),
-        "Alice".to_string(),
-        10.0,
-    ));
-    
-    blockchain.add_transaction(Transaction::new(
-        "Alice".to_string(),
-        "Bob".to_string(),
-        5.0,
-    ));
-    
     // Mine another block
     println!("\n📦 Mining second block...");
-    blockchain.mine_pending_transactions("Miner2".to_string());
-    
+    mine(&mut blockchain, "Miner2".to_string())?;
+
     // Display blockchain
-    blockchain.display_chain();
-    
+    blockchain.display_chain()?;
+
     // Check balances
     println!("\n💰 BALANCES:");
-    println!("Alice: {}", blockchain.get_balance("Alice"));
-    println!("Bob: {}", blockchain.get_balance("Bob"));
-    println!("Charlie: {}", blockchain.get_balance("Charlie"));
+    println!("Alice: {}", blockchain.get_balance(&alice.address()));
+    println!("Bob: {}", blockchain.get_balance(&bob.address()));
+    println!("Charlie: {}", blockchain.get_balance(&charlie.address()));
     println!("Miner1: {}", blockchain.get_balance("Miner1"));
     println!("Miner2: {}", blockchain.get_balance("Miner2"));
-    
+
     // Validate blockchain
     println!("\n✅ Is blockchain valid? {}", blockchain.is_chain_valid());
-    
-    // Try to modify a block (immutability demonstration)
+    println!("⛏️  Total chain work: {}", blockchain.chain_work());
+
+    // Verify a payment with only block headers (SPV)
+    let payment = blockchain
+        .get_blocks(2..3)?
+        .remove(0)
+        .transactions
+        .remove(0);
+    if let Some(proof) = blockchain.prove_transaction(&payment.txid())? {
+        let mut light_client = LightClient::new(blockchain.genesis.clone());
+        match light_client.verify(&payment, &proof) {
+            Ok(()) => println!(
+                "🔍 Light client confirmed payment in block #{}",
+                proof.height
+            ),
+            Err(error) => println!("🔍 Light client rejected proof: {}", error),
+        }
+
+        // Check Bob's balance against the state root in the tip header
+        let balance_proof = blockchain.prove_balance(&bob.address());
+        match light_client.verify_balance(&balance_proof) {
+            Ok(()) => println!(
+                "🔍 Light client confirmed Bob holds {}",
+                balance_proof.account.balance
+            ),
+            Err(error) => println!("🔍 Light client rejected balance proof: {}", error),
+        }
+    }
+
+    // Round-trip the chain through its JSON export
+    let mut json = Vec::new();
+    blockchain.export_json(&mut json)?;
+    let imported = Blockchain::import_json(json.as_slice())?;
+    println!(
+        "📤 Exported {} bytes of JSON and re-imported {} blocks",
+        json.len(),
+        imported.len()
+    );
+
+    // Try to modify a copy of the blocks (immutability demonstration)
     println!("\n🔧 Attempting to modify a block...");
-    if let Some(block) = blockchain.chain.get_mut(1) {
-        block.transactions[0].amount = 1000.0;
+    let mut blocks = blockchain.get_blocks(0..blockchain.len())?;
+    blocks[2].transactions[0].amount = coins("1000");
+
+    let errors = validation::validate_all(&blocks, &blockchain.genesis);
+    println!(
+        "✅ Is blockchain valid after modification? {}",
+        errors.is_empty()
+    );
+    for error in errors {
+        println!("   ❌ {}", error);
     }
-    
-    println!("✅ Is blockchain valid after modification? {}", blockchain.is_chain_valid());
-}
\ No newline at end of file
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+//! Binary Merkle trees over transaction hashes.
+//!
+//! Leaves are `SHA-256(0x00 || transaction encoding)` and interior nodes
+//! are `SHA-256(0x01 || left || right)`; the distinct prefixes keep a leaf
+//! from ever being reinterpreted as an interior node. When a level has an
+//! odd number of nodes the last one is carried up unchanged rather than
+//! duplicated, so two different transaction lists never share a root.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::encoding::Encode;
+use crate::transaction::Transaction;
+
+pub type Hash = [u8; 32];
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Root of a tree with no leaves.
+pub const EMPTY_ROOT: Hash = [0u8; 32];
+
+pub fn leaf_hash(transaction: &Transaction) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(transaction.encode());
+    hasher.finalize().into()
+}
+
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Which side of the running hash a proof sibling sits on.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProofStep {
+    pub sibling: Hash,
+    pub side: Side,
+}
+
+/// The path from one leaf to the root.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub steps: Vec<ProofStep>,
+}
+
+impl MerkleProof {
+    /// Folds `leaf` up through every step and returns the resulting root.
+    pub fn compute_root(&self, leaf: Hash) -> Hash {
+        self.steps.iter().fold(leaf, |acc, step| match step.side {
+            Side::Left => node_hash(&step.sibling, &acc),
+            Side::Right => node_hash(&acc, &step.sibling),
+        })
+    }
+
+    pub fn verify(&self, transaction: &Transaction, root: &Hash) -> bool {
+        self.compute_root(leaf_hash(transaction)) == *root
+    }
+}
+
+/// Every level of the tree, leaves first, kept so proofs can be produced
+/// without rehashing.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn from_transactions(transactions: &[Transaction]) -> Self {
+        Self::from_leaves(transactions.iter().map(leaf_hash).collect())
+    }
+
+    pub fn from_leaves(leaves: Vec<Hash>) -> Self {
+        let mut levels = vec![leaves];
+        loop {
+            let level = &levels[levels.len() - 1];
+            if level.len() <= 1 {
+                break;
+            }
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        MerkleTree { levels }
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or(EMPTY_ROOT)
+    }
+
+    pub fn leaf_count(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Inclusion proof for the leaf at `index`, or `None` if out of range.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.leaf_count() {
+            return None;
+        }
+
+        let mut steps = Vec::new();
+        let mut position = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = position ^ 1;
+            if let Some(hash) = level.get(sibling) {
+                let side = if sibling < position {
+                    Side::Left
+                } else {
+                    Side::Right
+                };
+                steps.push(ProofStep {
+                    sibling: *hash,
+                    side,
+                });
+            }
+            position /= 2;
+        }
+        Some(MerkleProof { steps })
+    }
+}
+
+pub fn merkle_root(transactions: &[Transaction]) -> Hash {
+    MerkleTree::from_transactions(transactions).root()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+
+    fn transactions(count: u64) -> Vec<Transaction> {
+        (0..count)
+            .map(|index| Transaction::coinbase("Miner".into(), Amount::from_units(index), index))
+            .collect()
+    }
+
+    #[test]
+    fn odd_nodes_are_carried_up_unchanged() {
+        let transactions = transactions(4);
+        let [a, b, c, _] = [0, 1, 2, 3].map(|index| leaf_hash(&transactions[index]));
+        assert_eq!(merkle_root(&[]), EMPTY_ROOT);
+        assert_eq!(merkle_root(&transactions[..1]), a);
+        assert_eq!(
+            merkle_root(&transactions[..3]),
+            node_hash(&node_hash(&a, &b), &c)
+        );
+
+        // Duplicating the last transaction must change the root.
+        let mut padded = transactions[..3].to_vec();
+        padded.push(transactions[2].clone());
+        assert_ne!(merkle_root(&padded), merkle_root(&transactions[..3]));
+    }
+
+    #[test]
+    fn every_leaf_proves_its_own_inclusion_only() {
+        for count in 1..=7 {
+            let transactions = transactions(count);
+            let tree = MerkleTree::from_transactions(&transactions);
+            let root = tree.root();
+            for (index, transaction) in transactions.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.verify(transaction, &root));
+                let other = &transactions[(index + 1) % transactions.len()];
+                assert_eq!(proof.verify(other, &root), count == 1);
+            }
+            assert_eq!(tree.proof(count as usize), None);
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Proof-of-work search.
+//!
+//! The nonce is the last field of the header encoding, so the SHA-256 state
+//! of everything before it is computed once per search and only the nonce
+//! is hashed per attempt. With several threads, thread `t` of `n` tries
+//! nonces `start + t`, `start + t + n`, ... and every thread stops once it
+//! passes the lowest solution found so far, so the result is always the
+//! first valid nonce after `start`, exactly as a single thread would find.
+//!
+//! `Block::mine_block` blocks until it succeeds, moving the timestamp on a
+//! second whenever every nonce fails. `Miner` runs the same
+//! search on a background thread, reports progress on a channel and stops
+//! when its `CancellationToken` is triggered.
+
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::block::{Block, BlockHeader};
+use crate::encoding::{self, Encode};
+use crate::merkle::Hash;
+use crate::target::Target;
+
+/// Number of mining threads to use when none is configured: one per
+/// available core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// A nonce meeting the target, and how many hashes the search took
+/// across all threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+    pub nonce: u64,
+    pub hashes: u64,
+}
+
+/// The header with everything but the nonce already hashed.
+struct HeaderTemplate {
+    prefix: Sha256,
+}
+
+impl HeaderTemplate {
+    fn new(header: &BlockHeader) -> Self {
+        let encoded = header.encode();
+        let mut prefix = Sha256::new();
+        prefix.update(&encoded[..encoded.len() - 8]);
+        HeaderTemplate { prefix }
+    }
+
+    fn hash(&self, nonce: u64) -> Hash {
+        let mut hasher = self.prefix.clone();
+        hasher.update(nonce.to_be_bytes());
+        hasher.finalize().into()
+    }
+}
+
+/// Shared flag that stops a search from another thread. Clones share the
+/// same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A snapshot of a running search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MiningProgress {
+    /// Nonces tried so far, across all threads.
+    pub hashes: u64,
+    /// Hashes per second since the search started.
+    pub hash_rate: f64,
+    /// The lowest header hash seen so far, as lowercase hex.
+    pub best_hash: String,
+    pub elapsed: Duration,
+}
+
+/// How a `Miner` run ended.
+#[derive(Debug, Clone)]
+pub enum MiningOutcome {
+    /// The block with a nonce and hash meeting the target.
+    Mined(Box<Block>),
+    /// The cancellation token was triggered first.
+    Cancelled,
+    /// No nonce from the block's starting nonce up to `u64::MAX` works.
+    Exhausted,
+}
+
+/// Proof-of-work search that runs off the calling thread and reports
+/// progress while it works.
+#[derive(Debug, Clone)]
+pub struct Miner {
+    pub threads: usize,
+    /// How often progress is reported.
+    pub report_interval: Duration,
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        Miner {
+            threads: default_threads(),
+            report_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl Miner {
+    /// Mines `block` at `target` on a background thread. Progress is
+    /// sent to `progress` until the search ends; a dropped receiver is
+    /// ignored. Triggering `token` stops the search.
+    pub fn spawn(
+        &self,
+        block: Block,
+        target: Target,
+        token: CancellationToken,
+        progress: Sender<MiningProgress>,
+    ) -> JoinHandle<MiningOutcome> {
+        let miner = self.clone();
+        thread::spawn(move || {
+            miner.mine(block, target, &token, |report| {
+                let _ = progress.send(report.clone());
+            })
+        })
+    }
+
+    /// Mines `block` at `target` on the calling thread, passing
+    /// progress to `on_progress` every `report_interval`.
+    pub fn mine(
+        &self,
+        mut block: Block,
+        target: Target,
+        token: &CancellationToken,
+        mut on_progress: impl FnMut(&MiningProgress),
+    ) -> MiningOutcome {
+        block.header.bits = target.to_bits();
+        let search = Search {
+            target,
+            threads: self.threads,
+            token,
+            report_interval: self.report_interval,
+        };
+        match search.run(&block.header, &mut on_progress) {
+            Some(solution) => {
+                block.header.nonce = solution.nonce;
+                block.hash = block.calculate_hash();
+                MiningOutcome::Mined(Box::new(block))
+            }
+            None if token.is_cancelled() => MiningOutcome::Cancelled,
+            None => MiningOutcome::Exhausted,
+        }
+    }
+}
+
+/// Finds the lowest nonce from `header.nonce` upwards whose header hash
+/// meets `target`, split across `threads` threads. Returns `None` if no
+/// nonce up to `u64::MAX` works.
+pub fn search(header: &BlockHeader, target: Target, threads: usize) -> Option<Solution> {
+    let token = CancellationToken::new();
+    let search = Search {
+        target,
+        threads,
+        token: &token,
+        report_interval: Duration::MAX,
+    };
+    search.run(header, &mut |_| {})
+}
+
+/// Hashes a worker counts locally before adding them to the shared total.
+const HASH_BATCH: u64 = 4096;
+
+struct Search<'a> {
+    target: Target,
+    threads: usize,
+    token: &'a CancellationToken,
+    report_interval: Duration,
+}
+
+impl Search<'_> {
+    fn run(
+        &self,
+        header: &BlockHeader,
+        on_progress: &mut dyn FnMut(&MiningProgress),
+    ) -> Option<Solution> {
+        let template = HeaderTemplate::new(header);
+        let start = header.nonce;
+        let threads = self.threads.max(1) as u64;
+        let best = AtomicU64::new(u64::MAX);
+        let found = AtomicBool::new(false);
+        let hashes = AtomicU64::new(0);
+        let best_hash = Mutex::new([0xff; 32]);
+        let started = Instant::now();
+
+        thread::scope(|scope| {
+            let (done, finished) = mpsc::channel();
+            for offset in 0..threads {
+                let done = done.clone();
+                let (template, best, found, hashes, best_hash) =
+                    (&template, &best, &found, &hashes, &best_hash);
+                scope.spawn(move || {
+                    let mut local_hashes = 0;
+                    let mut local_best = [0xff; 32];
+                    let mut nonce = start.checked_add(offset);
+                    while let Some(current) = nonce {
+                        if current > best.load(Ordering::Relaxed) || self.token.is_cancelled() {
+                            break;
+                        }
+                        let hash = template.hash(current);
+                        local_hashes += 1;
+                        if local_hashes == HASH_BATCH {
+                            hashes.fetch_add(local_hashes, Ordering::Relaxed);
+                            local_hashes = 0;
+                        }
+                        if hash < local_best {
+                            local_best = hash;
+                            let mut shared = best_hash.lock().expect("best hash lock poisoned");
+                            *shared = (*shared).min(hash);
+                        }
+                        if self.target.is_met_by(&hash) {
+                            best.fetch_min(current, Ordering::Relaxed);
+                            found.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                        nonce = current.checked_add(threads);
+                    }
+                    hashes.fetch_add(local_hashes, Ordering::Relaxed);
+                    let _ = done.send(());
+                });
+            }
+            drop(done);
+
+            let mut next_report = started.checked_add(self.report_interval);
+            loop {
+                let received = match next_report {
+                    Some(deadline) => {
+                        finished.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    }
+                    None => finished.recv().map_err(RecvTimeoutError::from),
+                };
+                match received {
+                    Ok(()) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {
+                        next_report =
+                            next_report.and_then(|at| at.checked_add(self.report_interval));
+                        let elapsed = started.elapsed();
+                        let total = hashes.load(Ordering::Relaxed);
+                        on_progress(&MiningProgress {
+                            hashes: total,
+                            hash_rate: total as f64 / elapsed.as_secs_f64(),
+                            best_hash: encoding::to_hex(
+                                &*best_hash.lock().expect("best hash lock poisoned"),
+                            ),
+                            elapsed,
+                        });
+                    }
+                }
+            }
+        });
+
+        // A nonce found before cancellation still counts.
+        found.into_inner().then(|| Solution {
+            nonce: best.into_inner(),
+            hashes: hashes.into_inner(),
+        })
+    }
+}
+
+impl Block {
+    /// Searches for a nonce whose header hash meets `target`, recording the
+    /// target in the header's `bits` so validators can check the work later.
+    /// Uses one thread per available core.
+    pub fn mine_block(&mut self, target: Target) {
+        self.mine_block_with_threads(target, default_threads());
+    }
+
+    /// `mine_block` split across `threads` threads. The block found is the
+    /// same for any thread count. If no nonce from the header's nonce up
+    /// works, the timestamp moves on a second and the search restarts at
+    /// nonce 0. Returns the number of hashes computed.
+    pub fn mine_block_with_threads(&mut self, target: Target, threads: usize) -> u64 {
+        self.header.bits = target.to_bits();
+        let mut hashes = 0u64;
+        loop {
+            if let Some(solution) = search(&self.header, target, threads) {
+                self.header.nonce = solution.nonce;
+                self.hash = self.calculate_hash();
+                return hashes.saturating_add(solution.hashes);
+            }
+            let tried = (u64::MAX - self.header.nonce).saturating_add(1);
+            hashes = hashes.saturating_add(tried);
+            self.header.timestamp += chrono::Duration::seconds(1);
+            self.header.nonce = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::target::U256;
+    use crate::transaction::Transaction;
+
+    fn block() -> Block {
+        let coinbase = Transaction::coinbase("Miner".into(), Amount::ZERO, 1);
+        let mut block = Block::new(1, vec![coinbase], "00ab".into());
+        block.header.timestamp = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        block
+    }
+
+    #[test]
+    fn every_thread_count_mines_the_same_block() {
+        let target = Target::from_leading_zeros(3);
+        let mut expected = block();
+        expected.mine_block_with_threads(target, 1);
+        assert!(target.is_met_by_hex(&expected.hash));
+        for threads in [2, 3, 8] {
+            let mut block = block();
+            block.mine_block_with_threads(target, threads);
+            assert_eq!(block.header.nonce, expected.header.nonce);
+            assert_eq!(block.hash, expected.hash);
+        }
+    }
+
+    #[test]
+    fn exhausted_nonces_move_the_timestamp_on() {
+        let target = Target::from_leading_zeros(3);
+        let mut block = block();
+        let timestamp = block.header.timestamp;
+        block.header.nonce = u64::MAX - 3;
+        assert_eq!(search(&block.header, target, 2), None);
+
+        block.mine_block_with_threads(target, 2);
+        assert_eq!(
+            block.header.timestamp,
+            timestamp + chrono::Duration::seconds(1)
+        );
+        assert_eq!(block.hash, block.calculate_hash());
+        assert!(target.is_met_by_hex(&block.hash));
+    }
+
+    #[test]
+    fn cancelled_miner_stops_without_a_block() {
+        // Only a hash of 0 or 1 would meet this, so in practice only
+        // cancellation ends the search.
+        let target = Target::from_value(U256::ONE).unwrap();
+        let token = CancellationToken::new();
+        let (progress, reports) = mpsc::channel();
+        let miner = Miner {
+            threads: 2,
+            report_interval: Duration::from_millis(10),
+        };
+        let handle = miner.spawn(block(), target, token.clone(), progress);
+        // Hashes are counted in batches, so early reports may show none.
+        assert!(reports.iter().any(|report| report.hashes > 0));
+        token.cancel();
+        assert!(matches!(handle.join().unwrap(), MiningOutcome::Cancelled));
+    }
+}
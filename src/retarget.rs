@@ -0,0 +1,147 @@
+//! Target retargeting from block timestamps.
+//!
+//! Chains whose genesis sets a `RetargetConfig` do not take their target
+//! from `Blockchain::target`. Instead every block at a multiple of
+//! `interval` looks at how long the preceding `interval` blocks took and
+//! scales its predecessor's target by the ratio of that time to
+//! `target_block_time` per block: blocks that came twice as fast halve the
+//! target, doubling the work. The ratio is clamped to `max_factor` either
+//! way, and the target never rises above the genesis target. Every other
+//! block keeps its predecessor's target.
+
+use serde::{Deserialize, Serialize};
+
+use crate::block::BlockHeader;
+use crate::genesis::GenesisConfig;
+use crate::target::{Target, U256};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RetargetConfig {
+    /// Blocks between retargets.
+    pub interval: u64,
+    /// Intended seconds between consecutive blocks.
+    pub target_block_time: u64,
+    /// Largest factor a single retarget scales the target by, in either
+    /// direction.
+    pub max_factor: u64,
+}
+
+impl Default for RetargetConfig {
+    fn default() -> Self {
+        RetargetConfig {
+            interval: 10,
+            target_block_time: 10,
+            max_factor: 4,
+        }
+    }
+}
+
+impl RetargetConfig {
+    /// Target of a block at `previous.len()` whose predecessors are
+    /// `previous`, given the genesis target `limit`.
+    pub fn next_target<H: AsRef<BlockHeader>>(&self, previous: &[H], limit: Target) -> Target {
+        let Some(last) = previous.last().map(AsRef::as_ref) else {
+            return limit;
+        };
+        let Some(last_target) = last.target() else {
+            return limit;
+        };
+        let height = previous.len() as u64;
+        let interval = self.interval.max(1);
+        if !height.is_multiple_of(interval) {
+            return last_target;
+        }
+
+        // Block 0's timestamp is fixed by the genesis config and says
+        // nothing about mining speed, so windows never start before block 1.
+        let first = height.saturating_sub(interval + 1).max(1);
+        let gaps = (height - 1).saturating_sub(first);
+        if gaps == 0 {
+            return last_target;
+        }
+        let first = previous[first as usize].as_ref();
+
+        let expected = gaps.saturating_mul(self.target_block_time).max(1);
+        let max_factor = self.max_factor.max(1);
+        let elapsed = (last.timestamp - first.timestamp).num_seconds().max(0) as u64;
+        let actual = elapsed
+            .clamp(expected / max_factor, expected.saturating_mul(max_factor))
+            .max(1);
+
+        let scaled = last_target
+            .value()
+            .checked_mul_u64(actual)
+            .map_or(limit.value(), |value| value.div_u64(expected));
+        Target::from_value(scaled.max(U256::ONE))
+            .expect("target is non-zero")
+            .min(limit)
+    }
+}
+
+impl GenesisConfig {
+    /// Target the block after `previous` must carry, or `None` if the chain
+    /// has no retarget rule and any target up to the genesis target is
+    /// accepted.
+    pub fn expected_target<H: AsRef<BlockHeader>>(&self, previous: &[H]) -> Option<Target> {
+        self.retarget
+            .as_ref()
+            .map(|retarget| retarget.next_target(previous, self.target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    use crate::block::Block;
+
+    /// Headers at `target` whose timestamps are `seconds`.
+    fn headers(seconds: &[i64], target: Target) -> Vec<BlockHeader> {
+        (0..)
+            .zip(seconds)
+            .map(|(index, seconds)| {
+                let mut header = Block::new(index, Vec::new(), "00ab".into()).header;
+                header.timestamp = DateTime::from_timestamp(*seconds, 0).unwrap();
+                header.bits = target.to_bits();
+                header
+            })
+            .collect()
+    }
+
+    fn scaled(target: Target, factor: u64, divisor: u64) -> Target {
+        let value = target.value().checked_mul_u64(factor).unwrap();
+        Target::from_value(value.div_u64(divisor)).unwrap()
+    }
+
+    #[test]
+    fn retargets_scale_with_block_times_within_bounds() {
+        let config = RetargetConfig {
+            interval: 4,
+            target_block_time: 10,
+            max_factor: 4,
+        };
+        let limit = Target::from_leading_zeros(2);
+        let target = Target::from_leading_zeros(4);
+        let next = |seconds: &[i64]| config.next_target(&headers(seconds, target), limit);
+
+        assert_eq!(config.next_target::<BlockHeader>(&[], limit), limit);
+        // Only blocks at a multiple of the interval retarget.
+        assert_eq!(next(&[0, 100, 101]), target);
+        assert_eq!(next(&[0, 100, 101, 102, 103]), target);
+
+        // The window runs from block 1 to block 3: two gaps of 10 seconds.
+        assert_eq!(next(&[0, 100, 110, 120]), target);
+        assert_eq!(next(&[0, 100, 105, 110]), scaled(target, 1, 2));
+        assert_eq!(next(&[0, 100, 120, 140]), scaled(target, 2, 1));
+
+        // Changes are clamped to `max_factor` either way.
+        assert_eq!(next(&[0, 100, 100, 100]), scaled(target, 1, 4));
+        assert_eq!(next(&[0, 100, 90, 80]), scaled(target, 1, 4));
+        assert_eq!(next(&[0, 100, 5000, 9000]), scaled(target, 4, 1));
+
+        // And never go above the genesis target.
+        let easy = config.next_target(&headers(&[0, 100, 5000, 9000], limit), limit);
+        assert_eq!(easy, limit);
+    }
+}
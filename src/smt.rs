@@ -0,0 +1,320 @@
+//! Sparse Merkle tree with 256-bit keys, used to commit to account state.
+//!
+//! Every key has a fixed path from the root: its bits, most significant
+//! first. A subtree holding no leaves hashes to all zeros and a subtree
+//! holding exactly one leaf hashes to that leaf, so only the branches where
+//! paths diverge are ever hashed. Interior nodes are hashed as in `merkle`;
+//! callers supply leaf hashes, which must be domain-separated from nodes.
+
+use serde::{Deserialize, Serialize};
+
+use crate::merkle::{node_hash, Hash, EMPTY_ROOT};
+
+fn bit(key: &Hash, depth: usize) -> bool {
+    (key[depth / 8] >> (7 - depth % 8)) & 1 == 1
+}
+
+/// One subtree. A subtree holding a single leaf is that leaf, however
+/// deep its key's path goes; a branch holds at least two leaves and caches
+/// its hash, so an update rehashes only the branches on its own path.
+#[derive(Debug, Clone, Default)]
+enum Node {
+    #[default]
+    Empty,
+    Leaf {
+        key: Hash,
+        leaf: Hash,
+    },
+    Branch {
+        left: Box<Node>,
+        right: Box<Node>,
+        hash: Hash,
+    },
+}
+
+impl Node {
+    fn hash(&self) -> Hash {
+        match self {
+            Node::Empty => EMPTY_ROOT,
+            Node::Leaf { leaf, .. } => *leaf,
+            Node::Branch { hash, .. } => *hash,
+        }
+    }
+
+    fn branch(left: Node, right: Node) -> Node {
+        let hash = node_hash(&left.hash(), &right.hash());
+        Node::Branch {
+            left: Box::new(left),
+            right: Box::new(right),
+            hash,
+        }
+    }
+
+    /// Sets `key` to `leaf` in this subtree at `depth`. Returns true if the
+    /// key was new.
+    fn insert(&mut self, key: Hash, leaf: Hash, depth: usize) -> bool {
+        match self {
+            Node::Empty => {
+                *self = Node::Leaf { key, leaf };
+                true
+            }
+            Node::Leaf {
+                key: existing,
+                leaf: existing_leaf,
+            } if *existing == key => {
+                *existing_leaf = leaf;
+                false
+            }
+            Node::Leaf { .. } => {
+                let Node::Leaf {
+                    key: other,
+                    leaf: other_leaf,
+                } = std::mem::take(self)
+                else {
+                    unreachable!("matched a leaf above");
+                };
+                let (mut left, mut right) = (Node::Empty, Node::Empty);
+                let side = if bit(&other, depth) {
+                    &mut right
+                } else {
+                    &mut left
+                };
+                *side = Node::Leaf {
+                    key: other,
+                    leaf: other_leaf,
+                };
+                let side = if bit(&key, depth) {
+                    &mut right
+                } else {
+                    &mut left
+                };
+                side.insert(key, leaf, depth + 1);
+                *self = Node::branch(left, right);
+                true
+            }
+            Node::Branch { left, right, hash } => {
+                let side = if bit(&key, depth) {
+                    &mut **right
+                } else {
+                    &mut **left
+                };
+                let added = side.insert(key, leaf, depth + 1);
+                *hash = node_hash(&left.hash(), &right.hash());
+                added
+            }
+        }
+    }
+
+    /// Removes `key` from this subtree at `depth`, collapsing a branch left
+    /// with a single leaf into that leaf. Returns true if the key was
+    /// present.
+    fn remove(&mut self, key: &Hash, depth: usize) -> bool {
+        match self {
+            Node::Empty => false,
+            Node::Leaf { key: existing, .. } => {
+                if existing != key {
+                    return false;
+                }
+                *self = Node::Empty;
+                true
+            }
+            Node::Branch { left, right, hash } => {
+                let side = if bit(key, depth) {
+                    &mut **right
+                } else {
+                    &mut **left
+                };
+                if !side.remove(key, depth + 1) {
+                    return false;
+                }
+                match (&mut **left, &mut **right) {
+                    (Node::Empty, only @ Node::Leaf { .. })
+                    | (only @ Node::Leaf { .. }, Node::Empty) => *self = std::mem::take(only),
+                    _ => *hash = node_hash(&left.hash(), &right.hash()),
+                }
+                true
+            }
+        }
+    }
+}
+
+/// The path to one key: sibling hashes from the root down to the subtree
+/// where the key's path ends, and the single leaf in that subtree, if any.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SmtProof {
+    pub siblings: Vec<Hash>,
+    /// `(key, leaf hash)` of the leaf ending the path. For a key that is
+    /// absent, this is another key sharing the path, or `None` if the
+    /// subtree is empty.
+    pub leaf: Option<(Hash, Hash)>,
+}
+
+impl SmtProof {
+    /// The root this proof implies if `key` holds `leaf` (`None` for
+    /// absent), or `None` if the proof cannot show that.
+    pub fn compute_root(&self, key: &Hash, leaf: Option<&Hash>) -> Option<Hash> {
+        let depth = self.siblings.len();
+        if depth > 256 {
+            return None;
+        }
+        let terminal = match (leaf, &self.leaf) {
+            (Some(claimed), Some((proven_key, proven)))
+                if proven_key == key && proven == claimed =>
+            {
+                *claimed
+            }
+            (None, None) => EMPTY_ROOT,
+            (None, Some((other, other_leaf)))
+                if other != key && (0..depth).all(|i| bit(other, i) == bit(key, i)) =>
+            {
+                *other_leaf
+            }
+            _ => return None,
+        };
+
+        let root = self
+            .siblings
+            .iter()
+            .enumerate()
+            .rev()
+            .fold(terminal, |acc, (i, sibling)| {
+                if bit(key, i) {
+                    node_hash(sibling, &acc)
+                } else {
+                    node_hash(&acc, sibling)
+                }
+            });
+        Some(root)
+    }
+
+    pub fn verify(&self, root: &Hash, key: &Hash, leaf: Option<&Hash>) -> bool {
+        self.compute_root(key, leaf).as_ref() == Some(root)
+    }
+}
+
+/// A sparse Merkle tree over `(key, leaf hash)` pairs, updated in place.
+#[derive(Debug, Clone, Default)]
+pub struct SparseMerkleTree {
+    root: Node,
+    len: usize,
+}
+
+impl SparseMerkleTree {
+    /// Builds the tree over `leaves`. Keys must be distinct.
+    pub fn new(leaves: impl IntoIterator<Item = (Hash, Hash)>) -> Self {
+        let mut tree = SparseMerkleTree::default();
+        for (key, leaf) in leaves {
+            tree.insert(key, leaf);
+        }
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn root(&self) -> Hash {
+        self.root.hash()
+    }
+
+    /// Sets the leaf at `key`, replacing any it held.
+    pub fn insert(&mut self, key: Hash, leaf: Hash) {
+        if self.root.insert(key, leaf, 0) {
+            self.len += 1;
+        }
+    }
+
+    /// Removes the leaf at `key`, if any.
+    pub fn remove(&mut self, key: &Hash) {
+        if self.root.remove(key, 0) {
+            self.len -= 1;
+        }
+    }
+
+    /// Proof of the leaf at `key`, or of its absence.
+    pub fn prove(&self, key: &Hash) -> SmtProof {
+        let mut node = &self.root;
+        let mut siblings = Vec::new();
+        let mut depth = 0;
+        while let Node::Branch { left, right, .. } = node {
+            if bit(key, depth) {
+                siblings.push(left.hash());
+                node = right;
+            } else {
+                siblings.push(right.hash());
+                node = left;
+            }
+            depth += 1;
+        }
+        let leaf = match node {
+            Node::Leaf { key, leaf } => Some((*key, *leaf)),
+            _ => None,
+        };
+        SmtProof { siblings, leaf }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A key that is all zeros except for `byte` at the end.
+    fn key(byte: u8) -> Hash {
+        let mut key = [0u8; 32];
+        key[31] = byte;
+        key
+    }
+
+    fn leaf(byte: u8) -> Hash {
+        [byte; 32]
+    }
+
+    #[test]
+    fn proofs_show_membership_and_absence() {
+        let keys = [key(0), key(1), key(2), [0xff; 32], [0x80; 32]];
+        let tree = SparseMerkleTree::new(keys.iter().map(|key| (*key, leaf(key[31]))));
+        let root = tree.root();
+        assert_eq!(tree.len(), keys.len());
+
+        for key in &keys {
+            let proof = tree.prove(key);
+            assert!(proof.verify(&root, key, Some(&leaf(key[31]))));
+            assert!(!proof.verify(&root, key, Some(&leaf(7))));
+            assert!(!proof.verify(&root, key, None));
+        }
+        // Keys 0 and 1 differ only in their last bit.
+        assert_eq!(tree.prove(&key(0)).siblings.len(), 256);
+
+        // An absent key ends in an empty subtree or at another leaf.
+        for absent in [key(3), [0x40; 32], [0xfe; 32]] {
+            let proof = tree.prove(&absent);
+            assert!(proof.verify(&root, &absent, None));
+            assert!(!proof.verify(&root, &absent, Some(&leaf(3))));
+        }
+    }
+
+    #[test]
+    fn root_depends_only_on_the_leaves() {
+        assert_eq!(SparseMerkleTree::default().root(), EMPTY_ROOT);
+        assert_eq!(SparseMerkleTree::new([(key(1), leaf(1))]).root(), leaf(1));
+
+        let leaves = [(key(0), leaf(0)), (key(1), leaf(1)), ([0xff; 32], leaf(2))];
+        let forward = SparseMerkleTree::new(leaves);
+        let backward = SparseMerkleTree::new(leaves.into_iter().rev());
+        assert_eq!(forward.root(), backward.root());
+
+        let mut tree = forward.clone();
+        tree.insert(key(2), leaf(9));
+        tree.insert(key(1), leaf(8));
+        assert_ne!(tree.root(), forward.root());
+        tree.insert(key(1), leaf(1));
+        tree.remove(&key(2));
+        tree.remove(&key(3));
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.root(), forward.root());
+    }
+}
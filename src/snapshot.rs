@@ -0,0 +1,255 @@
+//! JSON export and import of a whole chain, for exchanging fixtures.
+//!
+//! A snapshot carries the `GenesisConfig` its chain was built from, so an
+//! import checks the blocks against the same target, consensus and mining
+//! reward they were produced under. It is only accepted once the blocks
+//! pass full validation against that genesis and every pending transaction
+//! would be admitted by `Blockchain::add_transaction` again. Chains with a
+//! `ConsensusConfig::Custom` engine cannot be exported.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use crate::block::Block;
+use crate::chain::Blockchain;
+use crate::genesis::GenesisConfig;
+use crate::storage::{ChainStore, MemoryStore, StorageError};
+use crate::target::Target;
+use crate::transaction::Transaction;
+use crate::validation::{ChainError, ChainValidator, TransactionError};
+
+/// The serialized form of a `Blockchain`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainSnapshot {
+    pub genesis: GenesisConfig,
+    pub target: Target,
+    pub blocks: Vec<Block>,
+    #[serde(default)]
+    pub pending_transactions: Vec<Transaction>,
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    /// The input is not a well-formed snapshot.
+    Json(serde_json::Error),
+    /// The snapshot was exported from a chain with a different genesis.
+    Genesis,
+    /// The blocks do not form a valid chain.
+    Invalid(ChainError),
+    /// The mining target is easier than the genesis target.
+    Target { found: Target, limit: Target },
+    /// The pending transaction at `position` would not be admitted.
+    Pending {
+        position: usize,
+        error: TransactionError,
+    },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Json(error) => write!(f, "malformed chain snapshot: {}", error),
+            ImportError::Genesis => write!(f, "chain snapshot has a different genesis"),
+            ImportError::Invalid(error) => write!(f, "imported chain is invalid: {}", error),
+            ImportError::Target { found, limit } => write!(
+                f,
+                "mining target {} is easier than the genesis target {}",
+                found, limit
+            ),
+            ImportError::Pending { position, error } => {
+                write!(f, "pending transaction {}: {}", position, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImportError::Json(error) => Some(error),
+            ImportError::Invalid(error) => Some(error),
+            ImportError::Pending { error, .. } => Some(error),
+            ImportError::Genesis | ImportError::Target { .. } => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(error: serde_json::Error) -> Self {
+        ImportError::Json(error)
+    }
+}
+
+impl From<ChainError> for ImportError {
+    fn from(error: ChainError) -> Self {
+        ImportError::Invalid(error)
+    }
+}
+
+impl<S: ChainStore> Blockchain<S> {
+    /// Reads every block from the store into a snapshot.
+    pub fn snapshot(&self) -> Result<ChainSnapshot, StorageError> {
+        Ok(ChainSnapshot {
+            genesis: self.genesis.clone(),
+            target: self.target,
+            blocks: self.get_blocks(0..self.len())?,
+            pending_transactions: self.pending_transactions.clone(),
+        })
+    }
+
+    /// Writes the genesis, blocks, mining target and pending transactions
+    /// as pretty-printed JSON. Fails for a custom consensus engine.
+    pub fn export_json(&self, writer: impl Write) -> Result<(), serde_json::Error> {
+        let snapshot = self
+            .snapshot()
+            .map_err(|error| serde_json::Error::io(io::Error::other(error)))?;
+        serde_json::to_writer_pretty(writer, &snapshot)
+    }
+}
+
+impl Blockchain {
+    /// Reads a chain written by `export_json`, validating it against the
+    /// genesis it was exported with.
+    pub fn import_json(reader: impl Read) -> Result<Self, ImportError> {
+        let snapshot: ChainSnapshot = serde_json::from_reader(reader)?;
+        Self::from_snapshot(snapshot)
+    }
+
+    /// Like `import_json`, but only accepts a snapshot of a chain built
+    /// from `genesis`.
+    pub fn import_json_with_genesis(
+        reader: impl Read,
+        genesis: &GenesisConfig,
+    ) -> Result<Self, ImportError> {
+        let snapshot: ChainSnapshot = serde_json::from_reader(reader)?;
+        if snapshot.genesis != *genesis {
+            return Err(ImportError::Genesis);
+        }
+        Self::from_snapshot(snapshot)
+    }
+
+    /// Rebuilds an in-memory chain from `snapshot`, rejecting it unless the
+    /// blocks are valid under its genesis and every pending transaction is
+    /// admissible.
+    pub fn from_snapshot(snapshot: ChainSnapshot) -> Result<Self, ImportError> {
+        let genesis = snapshot.genesis;
+        let mut validator = ChainValidator::new(&genesis);
+        for block in &snapshot.blocks {
+            validator.push(block);
+        }
+        let state = validator.into_state()?;
+        if snapshot.target > genesis.target {
+            return Err(ImportError::Target {
+                found: snapshot.target,
+                limit: genesis.target,
+            });
+        }
+
+        let mut store = MemoryStore::new();
+        for block in snapshot.blocks {
+            store
+                .put_block(block)
+                .expect("validated blocks have consecutive heights");
+        }
+
+        let mut blockchain = Blockchain::from_parts(store, genesis, state);
+        blockchain.set_target(snapshot.target);
+        for (position, transaction) in snapshot.pending_transactions.into_iter().enumerate() {
+            blockchain
+                .add_transaction(transaction)
+                .map_err(|error| ImportError::Pending { position, error })?;
+        }
+        Ok(blockchain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::crypto::KeyPair;
+    use crate::testing::{coins, funded_genesis};
+
+    /// A chain under a non-default genesis with one mined payment and one
+    /// pending.
+    fn chain(alice: &KeyPair) -> Blockchain {
+        let genesis = GenesisConfig {
+            target: Target::from_leading_zeros(1),
+            mining_reward: coins(7),
+            ..funded_genesis(alice)
+        };
+        let mut chain = Blockchain::with_genesis(genesis);
+        let payment = Transaction::signed(alice, "Bob".into(), coins(1), Amount::ZERO, 0);
+        chain.add_transaction(payment).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        let payment = Transaction::signed(alice, "Bob".into(), coins(2), Amount::ZERO, 1);
+        chain.add_transaction(payment).unwrap();
+        chain
+    }
+
+    #[test]
+    fn import_uses_the_exported_genesis() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let chain = chain(&alice);
+        let mut json = Vec::new();
+        chain.export_json(&mut json).unwrap();
+
+        let imported = Blockchain::import_json(json.as_slice()).unwrap();
+        assert_eq!(imported.genesis, chain.genesis);
+        assert_eq!(imported.target(), chain.target());
+        assert_eq!(imported.tip_hash(), chain.tip_hash());
+        assert_eq!(imported.pending_transactions, chain.pending_transactions);
+
+        assert!(Blockchain::import_json_with_genesis(json.as_slice(), &chain.genesis).is_ok());
+        assert!(matches!(
+            Blockchain::import_json_with_genesis(json.as_slice(), &GenesisConfig::default()),
+            Err(ImportError::Genesis)
+        ));
+    }
+
+    #[test]
+    fn tampered_imports_are_rejected() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let snapshot = chain(&alice).snapshot().unwrap();
+        assert!(Blockchain::from_snapshot(snapshot.clone()).is_ok());
+
+        let mut tampered = snapshot.clone();
+        tampered.blocks[1].transactions[1].amount = coins(50);
+        assert!(matches!(
+            Blockchain::from_snapshot(tampered),
+            Err(ImportError::Invalid(_))
+        ));
+
+        // The mined coinbase claims more than a lowered reward allows.
+        let mut tampered = snapshot.clone();
+        tampered.genesis.mining_reward = coins(1);
+        assert!(matches!(
+            Blockchain::from_snapshot(tampered),
+            Err(ImportError::Invalid(ChainError::ExcessiveReward {
+                index: 1,
+                ..
+            }))
+        ));
+
+        let mut tampered = snapshot.clone();
+        tampered.target = Target::from_bits(0x207fffff).unwrap();
+        assert!(matches!(
+            Blockchain::from_snapshot(tampered),
+            Err(ImportError::Target { .. })
+        ));
+
+        let mut tampered = snapshot.clone();
+        let replay = tampered.pending_transactions[0].clone();
+        tampered.pending_transactions.push(replay);
+        assert!(matches!(
+            Blockchain::from_snapshot(tampered),
+            Err(ImportError::Pending { position: 1, .. })
+        ));
+
+        assert!(matches!(
+            Blockchain::import_json(&b"{\"blocks\": []}"[..]),
+            Err(ImportError::Json(_))
+        ));
+    }
+}
@@ -0,0 +1,306 @@
+//! Simplified payment verification: checking that a transaction was
+//! included in the chain, or what an address held, while holding only
+//! block headers.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::block::BlockHeader;
+use crate::encoding;
+use crate::genesis::GenesisConfig;
+use crate::merkle::{self, Hash, MerkleProof};
+use crate::smt::SmtProof;
+use crate::state::{self, Account};
+use crate::transaction::Transaction;
+use crate::validation::{self, ChainError};
+
+/// Everything a light client needs to confirm one transaction: the Merkle
+/// branch into its block and the header chain from genesis to the tip.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub height: u64,
+    pub position: usize,
+    pub merkle_proof: MerkleProof,
+    pub headers: Vec<BlockHeader>,
+}
+
+/// A claim that `address` held `account` after the block at `height`: the
+/// state tree branch to that block's `state_root` and the header chain from
+/// genesis to the tip. An address that never held anything proves the
+/// default account.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BalanceProof {
+    pub height: u64,
+    pub address: String,
+    pub account: Account,
+    pub state_proof: SmtProof,
+    pub headers: Vec<BlockHeader>,
+}
+
+impl BalanceProof {
+    /// The state root the claimed account implies, or `None` if the branch
+    /// cannot support the claim.
+    pub fn compute_root(&self) -> Option<Hash> {
+        let key = state::account_key(&self.address);
+        let leaf = state::account_leaf(&key, &self.account);
+        self.state_proof.compute_root(&key, leaf.as_ref())
+    }
+
+    pub fn verify(&self, state_root: &Hash) -> bool {
+        self.compute_root().as_ref() == Some(state_root)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpvError {
+    /// A header failed proof-of-work, linkage or genesis checks.
+    InvalidHeader(ChainError),
+    /// A header at an already known height has a different hash.
+    ConflictingHeader { height: u64 },
+    /// No header is known at `height`.
+    UnknownBlock { height: u64 },
+    /// The Merkle branch does not lead to the header's root.
+    NotIncluded { height: u64 },
+    /// The state branch does not lead to the header's state root.
+    BadStateProof { height: u64 },
+}
+
+impl fmt::Display for SpvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpvError::InvalidHeader(error) => write!(f, "invalid header: {}", error),
+            SpvError::ConflictingHeader { height } => {
+                write!(
+                    f,
+                    "header at height {} conflicts with the known chain",
+                    height
+                )
+            }
+            SpvError::UnknownBlock { height } => write!(f, "no header at height {}", height),
+            SpvError::NotIncluded { height } => write!(
+                f,
+                "transaction is not included in block at height {}",
+                height
+            ),
+            SpvError::BadStateProof { height } => write!(
+                f,
+                "account does not match the state root at height {}",
+                height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpvError {}
+
+impl From<ChainError> for SpvError {
+    fn from(error: ChainError) -> Self {
+        SpvError::InvalidHeader(error)
+    }
+}
+
+/// A client that tracks only the header chain and checks it the same way
+/// full validation does, minus anything that needs transactions.
+///
+/// Under proof of stake that includes the proposer check: a light client
+/// verifies each block's signature but cannot tell whether the signer was
+/// the selected proposer, so it must only sync headers from a full node it
+/// trusts.
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    genesis: GenesisConfig,
+    headers: Vec<BlockHeader>,
+    hashes: Vec<String>,
+}
+
+impl LightClient {
+    pub fn new(genesis: GenesisConfig) -> Self {
+        LightClient {
+            genesis,
+            headers: Vec::new(),
+            hashes: Vec::new(),
+        }
+    }
+
+    pub fn headers(&self) -> &[BlockHeader] {
+        &self.headers
+    }
+
+    /// Hash of the highest known header, if any.
+    pub fn tip_hash(&self) -> Option<&str> {
+        self.hashes.last().map(String::as_str)
+    }
+
+    /// Appends `header` to the tip after checking it.
+    pub fn add_header(&mut self, header: BlockHeader) -> Result<(), ChainError> {
+        let hash = header.calculate_hash();
+        let errors = validation::validate_header(
+            &header,
+            self.headers.len() as u64,
+            self.tip_hash(),
+            &self.genesis,
+        );
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error);
+        }
+        validation::check_timestamp(&header, &self.headers, Utc::now())?;
+        validation::check_seal(&header, &hash, &self.headers, &self.genesis)?;
+
+        self.headers.push(header);
+        self.hashes.push(hash);
+        Ok(())
+    }
+
+    /// Extends the known chain with `headers`. Headers at heights already
+    /// known must match what is stored.
+    pub fn sync(&mut self, headers: &[BlockHeader]) -> Result<(), SpvError> {
+        for header in headers {
+            let height = header.index;
+            match self.hashes.get(height as usize) {
+                Some(known) if *known != header.calculate_hash() => {
+                    return Err(SpvError::ConflictingHeader { height });
+                }
+                Some(_) => {}
+                None => self.add_header(header.clone())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `merkle_proof` places `transaction` in the block at
+    /// `height`, using only the stored header.
+    pub fn verify_inclusion(
+        &self,
+        transaction: &Transaction,
+        merkle_proof: &MerkleProof,
+        height: u64,
+    ) -> Result<(), SpvError> {
+        let header = self
+            .headers
+            .get(height as usize)
+            .ok_or(SpvError::UnknownBlock { height })?;
+        let root = merkle_proof.compute_root(merkle::leaf_hash(transaction));
+        if encoding::to_hex(&root) != header.merkle_root {
+            return Err(SpvError::NotIncluded { height });
+        }
+        Ok(())
+    }
+
+    /// Syncs the headers carried by `proof` and then checks inclusion.
+    pub fn verify(
+        &mut self,
+        transaction: &Transaction,
+        proof: &InclusionProof,
+    ) -> Result<(), SpvError> {
+        self.sync(&proof.headers)?;
+        self.verify_inclusion(transaction, &proof.merkle_proof, proof.height)
+    }
+
+    /// Syncs the headers carried by `proof` and then checks the claimed
+    /// account against the state root at its height.
+    pub fn verify_balance(&mut self, proof: &BalanceProof) -> Result<(), SpvError> {
+        self.sync(&proof.headers)?;
+        let height = proof.height;
+        let header = self
+            .headers
+            .get(height as usize)
+            .ok_or(SpvError::UnknownBlock { height })?;
+        let root = proof.compute_root().map(|root| encoding::to_hex(&root));
+        if root.as_deref() != Some(header.state_root.as_str()) {
+            return Err(SpvError::BadStateProof { height });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::Amount;
+    use crate::chain::Blockchain;
+    use crate::crypto::KeyPair;
+    use crate::testing::{coins, funded_genesis};
+
+    #[test]
+    fn light_client_checks_inclusion_against_its_headers() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let genesis = funded_genesis(&alice);
+        let mut chain = Blockchain::with_genesis(genesis.clone());
+        let payment = Transaction::signed(&alice, "Bob".into(), coins(1), Amount::ZERO, 0);
+        chain.add_transaction(payment.clone()).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+
+        let proof = chain.prove_transaction(&payment.txid()).unwrap().unwrap();
+        assert_eq!((proof.height, proof.position), (1, 0));
+        let mut client = LightClient::new(genesis.clone());
+        assert_eq!(client.verify(&payment, &proof), Ok(()));
+        assert_eq!(client.headers().len(), 3);
+
+        let forged = Transaction::signed(&alice, "Bob".into(), coins(2), Amount::ZERO, 0);
+        assert_eq!(
+            client.verify(&forged, &proof),
+            Err(SpvError::NotIncluded { height: 1 })
+        );
+        assert_eq!(
+            client.verify_inclusion(&payment, &proof.merkle_proof, 3),
+            Err(SpvError::UnknownBlock { height: 3 })
+        );
+
+        // A header from a competing chain at a known height.
+        let mut rival = Blockchain::with_genesis(genesis.clone());
+        rival.mine_pending_transactions("Rival".into()).unwrap();
+        assert_eq!(
+            client.sync(rival.headers()),
+            Err(SpvError::ConflictingHeader { height: 1 })
+        );
+
+        let mut tampered = proof.clone();
+        tampered.headers[2].previous_hash = "00ff".into();
+        assert!(matches!(
+            LightClient::new(genesis).verify(&payment, &tampered),
+            Err(SpvError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn light_client_checks_balances_against_the_state_root() {
+        let alice = KeyPair::from_secret_key(&[1u8; 32]);
+        let genesis = funded_genesis(&alice);
+        let mut chain = Blockchain::with_genesis(genesis.clone());
+        let payment = Transaction::signed(&alice, "Bob".into(), coins(1), Amount::ZERO, 0);
+        chain.add_transaction(payment).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+
+        let mut client = LightClient::new(genesis);
+        let proof = chain.prove_balance(&alice.address());
+        assert_eq!(proof.account.balance, coins(99));
+        assert_eq!(proof.account.nonce, 1);
+        assert_eq!(client.verify_balance(&proof), Ok(()));
+
+        // An address that never held anything proves the empty account.
+        let nobody = chain.prove_balance("Nobody");
+        assert_eq!(nobody.account, Account::default());
+        assert_eq!(client.verify_balance(&nobody), Ok(()));
+
+        let mut inflated = proof.clone();
+        inflated.account.balance = coins(100);
+        assert_eq!(
+            client.verify_balance(&inflated),
+            Err(SpvError::BadStateProof { height: 1 })
+        );
+        let mut borrowed = proof.clone();
+        borrowed.address = "Bob".into();
+        assert_eq!(
+            client.verify_balance(&borrowed),
+            Err(SpvError::BadStateProof { height: 1 })
+        );
+        let mut stale = proof;
+        stale.height = 0;
+        assert_eq!(
+            client.verify_balance(&stale),
+            Err(SpvError::BadStateProof { height: 0 })
+        );
+    }
+}
@@ -0,0 +1,477 @@
+//! Account state maintained block by block.
+//!
+//! `State` holds every address's balance, nonce, locked stake and stake
+//! still unbonding, plus the UTXO set in `LedgerMode::Utxo`, as of the last
+//! applied block, and which double signs have already been slashed.
+//! Applying a block records just enough to revert it, so the tip can be
+//! disconnected without replaying the chain.
+//!
+//! Accounts are committed to by a sparse Merkle tree keyed by
+//! `SHA-256(address)`, with leaves `SHA-256(0x00 || key || balance ||
+//! nonce || stake || unbonding || release_height)`. Empty accounts are
+//! left out, so the root depends only on what addresses actually hold. The
+//! tree is kept alongside the accounts and updated leaf by leaf as they
+//! change. Every block header carries the root of the state after that
+//! block.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::genesis::LedgerMode;
+use crate::merkle::Hash;
+use crate::smt::{SmtProof, SparseMerkleTree};
+use crate::transaction::Transaction;
+use crate::utxo::{UtxoDelta, UtxoSet};
+use crate::validation::{self, TransactionError};
+
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Blocks unstaked balance stays slashable before it can be spent, and how
+/// old double-sign evidence may be to still be reported.
+pub const UNBONDING_PERIOD: u64 = 10;
+
+/// What the chain knows about one address.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Account {
+    /// Confirmed balance; in the UTXO model, the sum of its unspent outputs.
+    pub balance: Amount,
+    /// Number of non-coinbase transactions the address has sent.
+    pub nonce: u64,
+    /// Balance locked as stake, which weighs the address's chance of
+    /// proposing blocks under proof of stake.
+    #[serde(default)]
+    pub stake: Amount,
+    /// Unstaked balance that can still be slashed and is not yet
+    /// spendable.
+    #[serde(default)]
+    pub unbonding: Amount,
+    /// Height of the first block in which `unbonding` is spendable.
+    #[serde(default)]
+    pub release_height: u64,
+}
+
+impl Account {
+    /// What the address can spend in the block at `height`: its balance
+    /// plus any unbonding stake released by then.
+    pub fn spendable(&self, height: u64) -> Amount {
+        if height >= self.release_height {
+            self.balance.saturating_add(self.unbonding)
+        } else {
+            self.balance
+        }
+    }
+
+    /// What a slash in the block at `height` would burn: the locked stake
+    /// plus any unbonding stake not yet released.
+    pub fn slashable(&self, height: u64) -> Amount {
+        if height >= self.release_height {
+            self.stake
+        } else {
+            self.stake.saturating_add(self.unbonding)
+        }
+    }
+
+    /// Moves unbonding stake released by the block at `height` into the
+    /// balance.
+    fn release(&mut self, height: u64) {
+        if !self.unbonding.is_zero() && height >= self.release_height {
+            self.balance = self.balance.saturating_add(self.unbonding);
+            self.unbonding = Amount::ZERO;
+            self.release_height = 0;
+        }
+    }
+}
+
+/// Position of `address` in the state tree.
+pub fn account_key(address: &str) -> Hash {
+    Sha256::digest(address.as_bytes()).into()
+}
+
+/// Leaf hash of `account` at `key`, or `None` for an empty account, which
+/// is not in the tree.
+pub fn account_leaf(key: &Hash, account: &Account) -> Option<Hash> {
+    if *account == Account::default() {
+        return None;
+    }
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(key);
+    hasher.update(account.balance.units().to_be_bytes());
+    hasher.update(account.nonce.to_be_bytes());
+    hasher.update(account.stake.units().to_be_bytes());
+    hasher.update(account.unbonding.units().to_be_bytes());
+    hasher.update(account.release_height.to_be_bytes());
+    Some(hasher.finalize().into())
+}
+
+/// How to revert one applied block.
+#[derive(Debug, Clone, Default)]
+struct BlockUndo {
+    /// Height of the block.
+    height: u64,
+    /// Every account the block touched, with its value before the touch.
+    accounts: Vec<(String, Option<Account>)>,
+    utxos: Vec<UtxoDelta>,
+    /// Offences the block slashed.
+    slashed: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+    mode: LedgerMode,
+    accounts: HashMap<String, Account>,
+    /// State tree over `accounts`, updated as each account changes.
+    tree: SparseMerkleTree,
+    utxos: UtxoSet,
+    /// Offender and height of every double sign already slashed.
+    slashed: HashSet<(String, u64)>,
+    undo: Vec<BlockUndo>,
+}
+
+impl State {
+    pub fn new(mode: LedgerMode) -> Self {
+        State {
+            mode,
+            accounts: HashMap::new(),
+            tree: SparseMerkleTree::default(),
+            utxos: UtxoSet::new(),
+            slashed: HashSet::new(),
+            undo: Vec::new(),
+        }
+    }
+
+    pub fn mode(&self) -> LedgerMode {
+        self.mode
+    }
+
+    /// Number of applied blocks, which is also the height of the next.
+    pub fn height(&self) -> u64 {
+        self.undo.len() as u64
+    }
+
+    /// Height of the block being applied, the last one begun.
+    fn block_height(&self) -> u64 {
+        self.height().saturating_sub(1)
+    }
+
+    pub fn account(&self, address: &str) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+
+    /// Balance of `address`, leaving out stake that is still unbonding or
+    /// has been released but not yet touched; see `spendable`.
+    pub fn balance(&self, address: &str) -> Amount {
+        self.account(address)
+            .map(|account| account.balance)
+            .unwrap_or_default()
+    }
+
+    /// What `address` can spend in the block at `height`.
+    pub fn spendable(&self, address: &str, height: u64) -> Amount {
+        self.account(address)
+            .map(|account| account.spendable(height))
+            .unwrap_or_default()
+    }
+
+    /// What a slash in the block at `height` would burn from `address`.
+    pub fn slashable(&self, address: &str, height: u64) -> Amount {
+        self.account(address)
+            .map(|account| account.slashable(height))
+            .unwrap_or_default()
+    }
+
+    /// Whether the double sign by `offender` at `height` has been slashed.
+    pub fn is_slashed(&self, offender: &str, height: u64) -> bool {
+        self.slashed.contains(&(offender.to_string(), height))
+    }
+
+    /// The nonce the next confirmed transaction from `address` must carry.
+    pub fn nonce(&self, address: &str) -> u64 {
+        self.account(address)
+            .map(|account| account.nonce)
+            .unwrap_or_default()
+    }
+
+    /// Balance `address` has locked as stake.
+    pub fn stake(&self, address: &str) -> Amount {
+        self.account(address)
+            .map(|account| account.stake)
+            .unwrap_or_default()
+    }
+
+    /// Every address with locked stake and how much, ordered by address.
+    pub fn stakes(&self) -> Vec<(String, Amount)> {
+        let mut stakes: Vec<_> = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| !account.stake.is_zero())
+            .map(|(address, account)| (address.clone(), account.stake))
+            .collect();
+        stakes.sort();
+        stakes
+    }
+
+    /// Every address that has sent or received anything, in no particular
+    /// order.
+    pub fn accounts(&self) -> impl Iterator<Item = (&str, &Account)> {
+        self.accounts
+            .iter()
+            .map(|(address, account)| (address.as_str(), account))
+    }
+
+    /// Root of the sparse Merkle tree over every non-empty account.
+    pub fn root(&self) -> Hash {
+        self.tree.root()
+    }
+
+    /// Proof of the account at `address`, or of its absence, against
+    /// `root()`.
+    pub fn prove(&self, address: &str) -> SmtProof {
+        self.tree.prove(&account_key(address))
+    }
+
+    /// Brings the leaf of `address` in the state tree up to date with its
+    /// account.
+    fn update_leaf(&mut self, address: &str) {
+        let key = account_key(address);
+        let account = self.accounts.get(address).copied().unwrap_or_default();
+        match account_leaf(&key, &account) {
+            Some(leaf) => self.tree.insert(key, leaf),
+            None => self.tree.remove(&key),
+        }
+    }
+
+    /// Unspent outputs. Empty unless the state uses `LedgerMode::Utxo`.
+    pub fn utxos(&self) -> &UtxoSet {
+        &self.utxos
+    }
+
+    /// Checks a user transaction against the current state, as part of the
+    /// block being applied: the ledger model, then the sender's nonce and
+    /// spendable balance, or its inputs. Unstakes must not exceed the
+    /// sender's stake, and slashes must report a recent offence not yet
+    /// slashed by an address that still has stake to burn.
+    pub fn check(&self, transaction: &Transaction) -> Result<(), TransactionError> {
+        validation::check_model(transaction, self.mode)?;
+        let height = self.block_height();
+        match self.mode {
+            LedgerMode::Account => {
+                let from = &transaction.from;
+                validation::check_nonce(transaction, self.nonce(from))?;
+                let balance = self.spendable(from, height);
+                if transaction.is_unstake() {
+                    return validation::check_unstake(transaction, self.stake(from), balance);
+                }
+                if let Some((offender, offence)) = transaction.offence() {
+                    let slashable = self.slashable(&offender, height);
+                    let slashed = self.is_slashed(&offender, offence);
+                    validation::check_slash(transaction, slashable, slashed, height)?;
+                }
+                validation::check_spend(transaction, balance)
+            }
+            LedgerMode::Utxo => self
+                .utxos
+                .check(transaction)
+                .map_err(TransactionError::Utxo),
+        }
+    }
+
+    /// Applies every transaction in `block`, which must already be valid
+    /// on top of this state.
+    pub fn apply_block(&mut self, block: &Block) {
+        self.begin_block();
+        for transaction in &block.transactions {
+            self.apply_transaction(transaction);
+        }
+    }
+
+    /// Starts a new block. Transactions applied until the next call are
+    /// reverted together by `revert_block`.
+    pub(crate) fn begin_block(&mut self) {
+        let height = self.height();
+        self.undo.push(BlockUndo {
+            height,
+            ..BlockUndo::default()
+        });
+    }
+
+    /// Reverts the most recently applied block. Returns false if no block
+    /// has been applied.
+    pub fn revert_block(&mut self) -> bool {
+        let Some(undo) = self.undo.pop() else {
+            return false;
+        };
+        for delta in undo.utxos.iter().rev() {
+            self.utxos.revert(delta);
+        }
+        for offence in undo.slashed {
+            self.slashed.remove(&offence);
+        }
+        for (address, previous) in undo.accounts.into_iter().rev() {
+            match previous {
+                Some(account) => self.accounts.insert(address.clone(), account),
+                None => self.accounts.remove(&address),
+            };
+            self.update_leaf(&address);
+        }
+        true
+    }
+
+    /// Applies one transaction as part of the block most recently begun,
+    /// or permanently if no block has been. Validation uses this to replay
+    /// a chain transaction by transaction.
+    pub(crate) fn apply_transaction(&mut self, transaction: &Transaction) {
+        let mut undo = self.undo.pop();
+        let mut permanent = BlockUndo::default();
+        let block_undo = undo.as_mut().unwrap_or(&mut permanent);
+        let touched = block_undo.accounts.len();
+        self.apply(transaction, block_undo);
+        for (address, _) in &block_undo.accounts[touched..] {
+            self.update_leaf(address);
+        }
+        self.undo.extend(undo);
+    }
+
+    fn apply(&mut self, transaction: &Transaction, undo: &mut BlockUndo) {
+        match self.mode {
+            LedgerMode::Account if transaction.is_coinbase() => {
+                let recipient = self.account_mut(&transaction.to, undo);
+                recipient.balance = recipient.balance.saturating_add(transaction.amount);
+            }
+            LedgerMode::Account if transaction.is_unstake() => {
+                let release_height = undo.height + UNBONDING_PERIOD;
+                let sender = self.account_mut(&transaction.from, undo);
+                sender.stake = sender.stake.saturating_sub(transaction.amount);
+                sender.balance = sender.balance.saturating_sub(transaction.fee);
+                sender.unbonding = sender.unbonding.saturating_add(transaction.amount);
+                sender.release_height = release_height;
+            }
+            LedgerMode::Account => {
+                let cost = transaction.amount.saturating_add(transaction.fee);
+                let sender = self.account_mut(&transaction.from, undo);
+                sender.balance = sender.balance.saturating_sub(cost);
+                if transaction.is_stake() {
+                    let sender = self.account_mut(&transaction.from, undo);
+                    sender.stake = sender.stake.saturating_add(transaction.amount);
+                } else if transaction.is_slash() {
+                    if let Some((offender, height)) = transaction.offence() {
+                        let account = self.account_mut(&offender, undo);
+                        account.stake = Amount::ZERO;
+                        account.unbonding = Amount::ZERO;
+                        account.release_height = 0;
+                        self.slashed.insert((offender.clone(), height));
+                        undo.slashed.push((offender, height));
+                    }
+                } else {
+                    let recipient = self.account_mut(&transaction.to, undo);
+                    recipient.balance = recipient.balance.saturating_add(transaction.amount);
+                }
+            }
+            LedgerMode::Utxo => {
+                let delta = self.utxos.apply(transaction);
+                for (_, output) in &delta.spent {
+                    let owner = self.account_mut(&output.address, undo);
+                    owner.balance = owner.balance.saturating_sub(output.amount);
+                }
+                for (_, output) in &delta.created {
+                    let owner = self.account_mut(&output.address, undo);
+                    owner.balance = owner.balance.saturating_add(output.amount);
+                }
+                undo.utxos.push(delta);
+            }
+        }
+        if !transaction.is_coinbase() {
+            self.account_mut(&transaction.from, undo).nonce += 1;
+        }
+    }
+
+    /// The account at `address`, created if missing, after recording its
+    /// current value in `undo` and releasing any unbonding stake due by the
+    /// block being applied.
+    fn account_mut(&mut self, address: &str, undo: &mut BlockUndo) -> &mut Account {
+        undo.accounts
+            .push((address.to_string(), self.accounts.get(address).copied()));
+        let account = self.accounts.entry(address.to_string()).or_default();
+        account.release(undo.height);
+        account
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::testing::{coins, double_sign};
+    use crate::transaction::GENESIS_ADDRESS;
+
+    /// A state where `proposer` staked 50 of its 100 coins in block 1 and
+    /// unstaked 20 of them in block 2.
+    fn unbonding_state(proposer: &KeyPair) -> State {
+        let mut state = State::new(LedgerMode::Account);
+        let genesis = Transaction::new(GENESIS_ADDRESS.to_string(), proposer.address(), coins(100));
+        let stake = Transaction::stake(proposer, coins(50), Amount::ZERO, 0);
+        let unstake = Transaction::unstake(proposer, coins(20), Amount::ZERO, 1);
+        for (height, transaction) in [genesis, stake, unstake].into_iter().enumerate() {
+            state.apply_block(&Block::new(height as u64, vec![transaction], String::new()));
+        }
+        state
+    }
+
+    #[test]
+    fn unbonding_stake_stays_slashable_until_released() {
+        let proposer = KeyPair::from_secret_key(&[1u8; 32]);
+        let address = proposer.address();
+        let state = unbonding_state(&proposer);
+        let account = state.account(&address).unwrap();
+        assert_eq!(account.stake, coins(30));
+        assert_eq!(account.unbonding, coins(20));
+        assert_eq!(account.release_height, 2 + UNBONDING_PERIOD);
+
+        let released = 2 + UNBONDING_PERIOD;
+        assert_eq!(state.spendable(&address, released - 1), coins(50));
+        assert_eq!(state.slashable(&address, released - 1), coins(50));
+        assert_eq!(state.spendable(&address, released), coins(70));
+        assert_eq!(state.slashable(&address, released), coins(30));
+    }
+
+    #[test]
+    fn slash_burns_unbonding_stake_once() {
+        let proposer = KeyPair::from_secret_key(&[1u8; 32]);
+        let reporter = KeyPair::from_secret_key(&[2u8; 32]);
+        let address = proposer.address();
+        let mut state = unbonding_state(&proposer);
+        let root = state.root();
+
+        let (first, second) = double_sign(&proposer, 2);
+        let slash = Transaction::slash(&reporter, first.clone(), second.clone(), Amount::ZERO, 0);
+        state.begin_block();
+        assert_eq!(state.check(&slash), Ok(()));
+        state.revert_block();
+        state.apply_block(&Block::new(3, vec![slash], String::new()));
+        assert_eq!(state.stake(&address), Amount::ZERO);
+        assert_eq!(state.slashable(&address, 3), Amount::ZERO);
+        assert_eq!(state.balance(&address), coins(50));
+        assert!(state.is_slashed(&address, 2));
+
+        // Unstaking before the report no longer helps, and the same offence
+        // cannot be reported again.
+        let replay = Transaction::slash(&reporter, second, first, Amount::ZERO, 1);
+        state.begin_block();
+        assert_eq!(
+            state.check(&replay),
+            Err(TransactionError::AlreadySlashed {
+                address: address.clone(),
+                height: 2,
+            })
+        );
+        state.revert_block();
+
+        assert!(state.revert_block());
+        assert!(!state.is_slashed(&address, 2));
+        assert_eq!(state.stake(&address), coins(30));
+        assert_eq!(state.root(), root);
+    }
+}
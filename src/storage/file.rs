@@ -0,0 +1,420 @@
+//! Durable, append-only block storage on the local filesystem.
+//!
+//! A store is a directory holding two files:
+//!
+//! - `blocks.dat`: an 8-byte magic, then one record per block:
+//!   `u32` payload length, 4-byte checksum (the first bytes of the
+//!   payload's SHA-256), then the block's canonical encoding.
+//! - `blocks.idx`: one 12-byte entry per block, `u64` record offset and
+//!   `u32` payload length, so any height can be read without a scan.
+//!
+//! Every append is fsync'd, block file first, and a failed append truncates
+//! whatever it wrote. A crash can therefore leave at most one torn record at
+//! the end of `blocks.dat` and an index that is behind or ahead of it.
+//! `FileStore::open` trusts the index as far as its entries line up with
+//! `blocks.dat`, scans only the records after that, truncates a torn final
+//! record and rewrites the index if it changed.
+//!
+//! Only headers, hashes and the lookup indexes are kept in memory; block
+//! bodies are read back from `blocks.dat` whenever they are asked for.
+
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{BlockIndex, ChainStore, StorageError, TxLocation};
+use crate::block::{Block, BlockHeader};
+use crate::encoding::{Decode, Encode};
+
+const BLOCKS_FILE: &str = "blocks.dat";
+const INDEX_FILE: &str = "blocks.idx";
+const MAGIC: &[u8; 8] = b"CFBLK006";
+const RECORD_HEADER_LEN: u64 = 8;
+const INDEX_ENTRY_LEN: u64 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IndexEntry {
+    offset: u64,
+    len: u32,
+}
+
+impl IndexEntry {
+    fn to_bytes(self) -> [u8; INDEX_ENTRY_LEN as usize] {
+        let mut bytes = [0u8; INDEX_ENTRY_LEN as usize];
+        bytes[..8].copy_from_slice(&self.offset.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.len.to_be_bytes());
+        bytes
+    }
+}
+
+fn checksum(payload: &[u8]) -> [u8; 4] {
+    let digest = Sha256::digest(payload);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+/// An append-only block file plus its offset index.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+    blocks: File,
+    /// A second handle on `blocks.dat` for reads through `&self`.
+    reader: Mutex<File>,
+    index: File,
+    entries: Vec<IndexEntry>,
+    lookup: BlockIndex,
+}
+
+impl FileStore {
+    /// Opens the store in `dir`, creating it if needed and recovering from
+    /// any torn write left by a crash.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::open_with(dir, |_| ())
+    }
+
+    /// Like `open`, also handing every stored block to `visit` in height
+    /// order as the in-memory indexes are rebuilt, so a caller can replay
+    /// the chain without reading it from disk again.
+    pub fn open_with(
+        dir: impl AsRef<Path>,
+        mut visit: impl FnMut(&Block),
+    ) -> Result<Self, StorageError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let blocks_path = dir.join(BLOCKS_FILE);
+        let mut blocks = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&blocks_path)?;
+        let index = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(INDEX_FILE))?;
+
+        if blocks.metadata()?.len() < MAGIC.len() as u64 {
+            // New store, or a crash before the magic was durable.
+            blocks.set_len(0)?;
+            blocks.write_all(MAGIC)?;
+            blocks.sync_all()?;
+            sync_dir(&dir)?;
+        } else {
+            let mut magic = [0u8; 8];
+            blocks.seek(SeekFrom::Start(0))?;
+            blocks.read_exact(&mut magic)?;
+            if &magic != MAGIC {
+                return Err(StorageError::BadMagic(blocks_path));
+            }
+        }
+
+        let mut store = FileStore {
+            dir,
+            reader: Mutex::new(File::open(&blocks_path)?),
+            blocks,
+            index,
+            entries: Vec::new(),
+            lookup: BlockIndex::default(),
+        };
+        store.recover()?;
+        for height in 0..store.entries.len() as u64 {
+            let block = store.read_block(height)?;
+            visit(&block);
+            store.lookup.insert(&block);
+        }
+        Ok(store)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Takes the entries of `blocks.idx` that describe consecutive records
+    /// within `blocks.dat`, scans the records after them, truncates a torn
+    /// final record and rewrites the index if it disagrees.
+    fn recover(&mut self) -> Result<(), StorageError> {
+        let file_len = self.blocks.metadata()?.len();
+        let indexed = self.read_index()?;
+        let mut entries = Vec::new();
+        let mut offset = MAGIC.len() as u64;
+
+        for entry in &indexed {
+            let end = entry.offset + RECORD_HEADER_LEN + u64::from(entry.len);
+            if entry.offset != offset || end > file_len {
+                break;
+            }
+            entries.push(*entry);
+            offset = end;
+        }
+
+        // Records appended after the last durable index entry.
+        while offset + RECORD_HEADER_LEN <= file_len {
+            let mut header = [0u8; RECORD_HEADER_LEN as usize];
+            self.blocks.seek(SeekFrom::Start(offset))?;
+            self.blocks.read_exact(&mut header)?;
+            let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+            let end = offset + RECORD_HEADER_LEN + u64::from(len);
+            if end > file_len {
+                break;
+            }
+            let mut payload = vec![0u8; len as usize];
+            self.blocks.read_exact(&mut payload)?;
+            if checksum(&payload) != header[4..] {
+                if end == file_len {
+                    break;
+                }
+                return Err(StorageError::Corrupt {
+                    height: entries.len() as u64,
+                });
+            }
+            entries.push(IndexEntry { offset, len });
+            offset = end;
+        }
+
+        if offset != file_len {
+            self.blocks.set_len(offset)?;
+            self.blocks.sync_all()?;
+        }
+
+        if indexed != entries {
+            let bytes: Vec<u8> = entries.iter().flat_map(|entry| entry.to_bytes()).collect();
+            self.index.set_len(0)?;
+            self.index.seek(SeekFrom::Start(0))?;
+            self.index.write_all(&bytes)?;
+            self.index.sync_all()?;
+        }
+
+        self.entries = entries;
+        Ok(())
+    }
+
+    fn read_index(&mut self) -> Result<Vec<IndexEntry>, StorageError> {
+        let mut bytes = Vec::new();
+        self.index.seek(SeekFrom::Start(0))?;
+        self.index.read_to_end(&mut bytes)?;
+        Ok(bytes
+            .chunks_exact(INDEX_ENTRY_LEN as usize)
+            .map(|entry| IndexEntry {
+                offset: u64::from_be_bytes(entry[..8].try_into().unwrap()),
+                len: u32::from_be_bytes(entry[8..].try_into().unwrap()),
+            })
+            .collect())
+    }
+
+    /// Durably appends `block` to `blocks.dat` and the index. Writes go
+    /// right after the last stored record, so leftovers of an earlier failed
+    /// append are overwritten rather than built upon.
+    fn append(&mut self, block: &Block) -> Result<(), StorageError> {
+        let payload = block.encode();
+        let len = u32::try_from(payload.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "block too large"))?;
+        let offset = self.entries.last().map_or(MAGIC.len() as u64, |entry| {
+            entry.offset + RECORD_HEADER_LEN + u64::from(entry.len)
+        });
+        let index_len = self.entries.len() as u64 * INDEX_ENTRY_LEN;
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN as usize + payload.len());
+        record.extend_from_slice(&len.to_be_bytes());
+        record.extend_from_slice(&checksum(&payload));
+        record.extend_from_slice(&payload);
+        let entry = IndexEntry { offset, len };
+
+        let written = write_at(&mut self.blocks, offset, &record)
+            .and_then(|()| write_at(&mut self.index, index_len, &entry.to_bytes()));
+        if let Err(error) = written {
+            // Best effort: `open` would otherwise find the partial record
+            // before the next one and refuse the store as corrupt.
+            let _ = self
+                .blocks
+                .set_len(offset)
+                .and_then(|()| self.blocks.sync_all());
+            let _ = self
+                .index
+                .set_len(index_len)
+                .and_then(|()| self.index.sync_all());
+            return Err(error.into());
+        }
+
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Reads the block at `height` from disk through the index, checking
+    /// its record against the entry and its checksum.
+    pub fn read_block(&self, height: u64) -> Result<Block, StorageError> {
+        let entry = *self
+            .entries
+            .get(height as usize)
+            .ok_or(StorageError::NotFound { height })?;
+        let mut record = vec![0u8; RECORD_HEADER_LEN as usize + entry.len as usize];
+        {
+            let mut reader = self.reader.lock().expect("block reader lock poisoned");
+            reader.seek(SeekFrom::Start(entry.offset))?;
+            reader.read_exact(&mut record)?;
+        }
+        let (header, payload) = record.split_at(RECORD_HEADER_LEN as usize);
+        if header[..4] != entry.len.to_be_bytes() || header[4..] != checksum(payload) {
+            return Err(StorageError::Corrupt { height });
+        }
+        Block::decode(payload).map_err(|error| StorageError::Decode { height, error })
+    }
+}
+
+impl ChainStore for FileStore {
+    fn len(&self) -> u64 {
+        self.lookup.len()
+    }
+
+    fn headers(&self) -> &[BlockHeader] {
+        &self.lookup.headers
+    }
+
+    fn get_hash(&self, height: u64) -> Option<&str> {
+        self.lookup.get_hash(height)
+    }
+
+    fn get_block(&self, height: u64) -> Result<Option<Block>, StorageError> {
+        if height >= self.len() {
+            return Ok(None);
+        }
+        self.read_block(height).map(Some)
+    }
+
+    fn get_height(&self, hash: &str) -> Option<u64> {
+        self.lookup.heights.get(hash).copied()
+    }
+
+    fn get_tx_location(&self, txid: &str) -> Option<TxLocation> {
+        self.lookup.transactions.get(txid).copied()
+    }
+
+    /// Returns only once `block` is durable.
+    fn put_block(&mut self, block: Block) -> Result<(), StorageError> {
+        self.lookup.check_next_height(&block)?;
+        self.append(&block)?;
+        self.lookup.insert(&block);
+        Ok(())
+    }
+
+    /// Truncates the block file before the index, so a crash in between
+    /// leaves an index that `open` rebuilds.
+    fn pop_block(&mut self) -> Result<Option<Block>, StorageError> {
+        let Some(entry) = self.entries.last().copied() else {
+            return Ok(None);
+        };
+        let block = self.read_block(self.entries.len() as u64 - 1)?;
+        self.blocks.set_len(entry.offset)?;
+        self.blocks.sync_all()?;
+        let remaining = self.entries.len() as u64 - 1;
+        self.index.set_len(remaining * INDEX_ENTRY_LEN)?;
+        self.index.sync_all()?;
+
+        self.entries.pop();
+        self.lookup.remove(&block);
+        Ok(Some(block))
+    }
+}
+
+/// Writes `bytes` at `offset`, drops anything after them and syncs.
+fn write_at(file: &mut File, offset: u64, bytes: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(bytes)?;
+    file.set_len(offset + bytes.len() as u64)?;
+    file.sync_all()
+}
+
+/// Makes newly created directory entries durable. Directories cannot be
+/// opened for syncing on every platform, so failures to open are ignored.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    match File::open(dir) {
+        Ok(handle) => handle.sync_all().or(Ok(())),
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Blockchain;
+
+    fn append(path: &Path, bytes: &[u8]) {
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(bytes)
+            .unwrap();
+    }
+
+    #[test]
+    fn open_truncates_a_torn_final_record() {
+        let dir = std::env::temp_dir().join(format!("chainforge-torn-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let blocks_path = dir.join(BLOCKS_FILE);
+        let index_path = dir.join(INDEX_FILE);
+
+        let mut chain = Blockchain::open(&dir).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        let tip = chain.tip_hash().to_string();
+        drop(chain);
+        let blocks_len = fs::metadata(&blocks_path).unwrap().len();
+        let index_len = fs::metadata(&index_path).unwrap().len();
+
+        // A crash partway through a record, after its index entry was
+        // written.
+        let entry = IndexEntry {
+            offset: blocks_len,
+            len: 100,
+        };
+        append(
+            &blocks_path,
+            &[&100u32.to_be_bytes()[..], &[0u8; 14]].concat(),
+        );
+        append(&index_path, &entry.to_bytes());
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.tip_hash(), Some(tip.as_str()));
+        assert_eq!(fs::metadata(&blocks_path).unwrap().len(), blocks_len);
+        assert_eq!(fs::metadata(&index_path).unwrap().len(), index_len);
+        drop(store);
+
+        // A complete record whose payload did not reach the disk.
+        append(&blocks_path, &[&4u32.to_be_bytes()[..], &[0u8; 8]].concat());
+        let mut chain = Blockchain::open(&dir).unwrap();
+        assert_eq!(chain.len(), 3);
+        assert_eq!(fs::metadata(&blocks_path).unwrap().len(), blocks_len);
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        drop(chain);
+
+        let store = FileStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.read_block(2).unwrap().hash, tip);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reopening_replays_each_block_once() {
+        let dir = std::env::temp_dir().join(format!("chainforge-replay-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut chain = Blockchain::open(&dir).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        let root = chain.state().root();
+        drop(chain);
+
+        let mut heights = Vec::new();
+        FileStore::open_with(&dir, |block| heights.push(block.header.index)).unwrap();
+        assert_eq!(heights, [0, 1, 2]);
+
+        let chain = Blockchain::open(&dir).unwrap();
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain.state().root(), root);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+//! Pluggable block storage.
+//!
+//! A `Blockchain` keeps its blocks, their hash and txid indexes and its tip
+//! in a `ChainStore`. Stores keep every header and the indexes in memory,
+//! so consensus rules can look back along the chain cheaply, and hand out
+//! blocks by height. `MemoryStore` holds whole blocks in memory and is the
+//! default; `FileStore` writes every block to disk before accepting it and
+//! reads bodies back from disk on demand.
+
+mod file;
+
+pub use file::FileStore;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::block::{Block, BlockHeader};
+use crate::encoding::DecodeError;
+use crate::validation::ChainError;
+
+/// Where a transaction sits in the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TxLocation {
+    pub height: u64,
+    pub position: usize,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    /// `blocks.dat` does not start with the expected magic bytes.
+    BadMagic(PathBuf),
+    /// A record before the tail fails its checksum, so this is not a torn
+    /// write and cannot be repaired by truncation.
+    Corrupt {
+        height: u64,
+    },
+    /// A complete record failed to decode.
+    Decode {
+        height: u64,
+        error: DecodeError,
+    },
+    /// The stored blocks do not form a valid chain.
+    Invalid(ChainError),
+    /// No block is stored at `height`.
+    NotFound {
+        height: u64,
+    },
+    /// A block was put at `found` while the store expected the next height.
+    OutOfOrder {
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(error) => write!(f, "storage I/O error: {}", error),
+            StorageError::BadMagic(path) => {
+                write!(f, "{} is not a chainforge block file", path.display())
+            }
+            StorageError::Corrupt { height } => {
+                write!(f, "stored block #{} fails its checksum", height)
+            }
+            StorageError::Decode { height, error } => {
+                write!(f, "stored block #{} is corrupt: {}", height, error)
+            }
+            StorageError::Invalid(error) => write!(f, "stored chain is invalid: {}", error),
+            StorageError::NotFound { height } => write!(f, "no stored block #{}", height),
+            StorageError::OutOfOrder { expected, found } => write!(
+                f,
+                "cannot store block #{}: next height is {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StorageError::Io(error) => Some(error),
+            StorageError::Decode { error, .. } => Some(error),
+            StorageError::Invalid(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for StorageError {
+    fn from(error: io::Error) -> Self {
+        StorageError::Io(error)
+    }
+}
+
+/// Storage for a single linear chain of blocks.
+///
+/// Blocks are only ever put at the next height and removed from the tip,
+/// so the tip is always the last block. Stores index every block they
+/// accept by hash and every transaction by txid.
+pub trait ChainStore {
+    /// Number of stored blocks.
+    fn len(&self) -> u64;
+
+    /// The header of every stored block, in height order.
+    fn headers(&self) -> &[BlockHeader];
+
+    /// Hash of the block at `height`.
+    fn get_hash(&self, height: u64) -> Option<&str>;
+
+    /// Reads the block at `height`, or `None` above the tip.
+    fn get_block(&self, height: u64) -> Result<Option<Block>, StorageError>;
+
+    /// Height of the block whose hash is `hash`.
+    fn get_height(&self, hash: &str) -> Option<u64>;
+
+    /// Location of the first transaction whose txid is `txid`.
+    fn get_tx_location(&self, txid: &str) -> Option<TxLocation>;
+
+    /// Stores `block` as the new tip. Its index must be the next height.
+    fn put_block(&mut self, block: Block) -> Result<(), StorageError>;
+
+    /// Removes and returns the tip, or `None` if the store is empty.
+    fn pop_block(&mut self) -> Result<Option<Block>, StorageError>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get_header(&self, height: u64) -> Option<&BlockHeader> {
+        self.headers().get(usize::try_from(height).ok()?)
+    }
+
+    /// Reads the stored blocks whose heights are in `range`, in order.
+    fn get_blocks(&self, range: Range<u64>) -> Result<Vec<Block>, StorageError> {
+        (range.start..range.end.min(self.len()))
+            .map(|height| {
+                self.get_block(height)?
+                    .ok_or(StorageError::NotFound { height })
+            })
+            .collect()
+    }
+
+    /// Hash of the tip, or `None` if the store is empty.
+    fn tip_hash(&self) -> Option<&str> {
+        self.get_hash(self.len().checked_sub(1)?)
+    }
+}
+
+/// What the store implementations keep in memory for every block: its
+/// header and hash, plus hash and txid lookups.
+#[derive(Debug, Default, Clone)]
+struct BlockIndex {
+    headers: Vec<BlockHeader>,
+    hashes: Vec<String>,
+    heights: HashMap<String, u64>,
+    transactions: HashMap<String, TxLocation>,
+}
+
+impl BlockIndex {
+    fn insert(&mut self, block: &Block) {
+        let height = block.header.index;
+        self.headers.push(block.header.clone());
+        self.hashes.push(block.hash.clone());
+        self.heights.insert(block.hash.clone(), height);
+        for (position, transaction) in block.transactions.iter().enumerate() {
+            // Identical transactions share a txid; the earliest one wins.
+            self.transactions
+                .entry(transaction.txid())
+                .or_insert(TxLocation { height, position });
+        }
+    }
+
+    /// Forgets `block`, which must be the last block inserted.
+    fn remove(&mut self, block: &Block) {
+        let height = block.header.index;
+        self.headers.pop();
+        self.hashes.pop();
+        self.heights.remove(&block.hash);
+        for transaction in &block.transactions {
+            let txid = transaction.txid();
+            if self.transactions.get(&txid).map(|location| location.height) == Some(height) {
+                self.transactions.remove(&txid);
+            }
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.headers.len() as u64
+    }
+
+    fn get_hash(&self, height: u64) -> Option<&str> {
+        self.hashes
+            .get(usize::try_from(height).ok()?)
+            .map(String::as_str)
+    }
+
+    fn check_next_height(&self, block: &Block) -> Result<(), StorageError> {
+        let expected = self.len();
+        if block.header.index != expected {
+            return Err(StorageError::OutOfOrder {
+                expected,
+                found: block.header.index,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the chain in a `Vec<Block>`. Nothing survives the process.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    blocks: Vec<Block>,
+    index: BlockIndex,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn len(&self) -> u64 {
+        self.index.len()
+    }
+
+    fn headers(&self) -> &[BlockHeader] {
+        &self.index.headers
+    }
+
+    fn get_hash(&self, height: u64) -> Option<&str> {
+        self.index.get_hash(height)
+    }
+
+    fn get_block(&self, height: u64) -> Result<Option<Block>, StorageError> {
+        Ok(usize::try_from(height)
+            .ok()
+            .and_then(|height| self.blocks.get(height))
+            .cloned())
+    }
+
+    fn get_height(&self, hash: &str) -> Option<u64> {
+        self.index.heights.get(hash).copied()
+    }
+
+    fn get_tx_location(&self, txid: &str) -> Option<TxLocation> {
+        self.index.transactions.get(txid).copied()
+    }
+
+    fn put_block(&mut self, block: Block) -> Result<(), StorageError> {
+        self.index.check_next_height(&block)?;
+        self.index.insert(&block);
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    fn pop_block(&mut self) -> Result<Option<Block>, StorageError> {
+        let block = self.blocks.pop();
+        if let Some(block) = &block {
+            self.index.remove(block);
+        }
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Blockchain;
+
+    /// Puts `blocks` into `store`, pops the tip and puts it back, checking
+    /// what the store reports along the way.
+    fn exercise(store: &mut impl ChainStore, blocks: &[Block]) {
+        assert!(store.is_empty());
+        assert!(matches!(
+            store.put_block(blocks[1].clone()),
+            Err(StorageError::OutOfOrder {
+                expected: 0,
+                found: 1
+            })
+        ));
+        for block in blocks {
+            store.put_block(block.clone()).unwrap();
+        }
+
+        let tip = blocks.last().unwrap();
+        assert_eq!(store.len(), blocks.len() as u64);
+        assert_eq!(store.tip_hash(), Some(tip.hash.as_str()));
+        let headers: Vec<_> = blocks.iter().map(|block| block.header.clone()).collect();
+        assert_eq!(store.headers(), headers);
+        for (height, block) in (0..).zip(blocks) {
+            assert_eq!(store.get_hash(height), Some(block.hash.as_str()));
+            assert_eq!(store.get_height(&block.hash), Some(height));
+            assert_eq!(store.get_header(height), Some(&block.header));
+            let stored = store.get_block(height).unwrap().unwrap();
+            assert_eq!(stored.hash, block.hash);
+            assert_eq!(stored.transactions, block.transactions);
+        }
+        assert!(store.get_block(store.len()).unwrap().is_none());
+        let range = store.get_blocks(1..10).unwrap();
+        assert_eq!(range.len(), blocks.len() - 1);
+
+        let popped = store.pop_block().unwrap().unwrap();
+        assert_eq!(popped.hash, tip.hash);
+        assert_eq!(store.len(), blocks.len() as u64 - 1);
+        assert_eq!(store.get_height(&tip.hash), None);
+        store.put_block(popped).unwrap();
+        assert_eq!(store.tip_hash(), Some(tip.hash.as_str()));
+    }
+
+    #[test]
+    fn memory_and_file_stores_behave_alike() {
+        let mut chain = Blockchain::new();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        let blocks = chain.get_blocks(0..3).unwrap();
+
+        exercise(&mut MemoryStore::new(), &blocks);
+
+        let dir = std::env::temp_dir().join(format!("chainforge-parity-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        exercise(&mut FileStore::open(&dir).unwrap(), &blocks);
+        let reopened = FileStore::open(&dir).unwrap();
+        assert_eq!(reopened.headers(), chain.headers());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
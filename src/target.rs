@@ -0,0 +1,378 @@
+//! 256-bit proof-of-work targets and chain work.
+//!
+//! A block's hash, read as a big-endian 256-bit integer, must be at most
+//! its target. Headers carry the target in the compact `bits` form: the
+//! high byte is a length in bytes and the low three bytes the most
+//! significant digits, so `0x1d00ffff` is `0x00ffff * 256^(0x1d - 3)`. Only
+//! canonical, positive, non-zero encodings are accepted, and every `Target`
+//! round-trips through `bits` exactly.
+//!
+//! The work a block proves is the expected number of hashes needed to meet
+//! its target, `2^256 / (target + 1)`. Summed over a chain it gives the
+//! chain's cumulative work, which fork choice compares.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Div, Not, Shl, Shr};
+
+use crate::encoding;
+use crate::merkle::Hash;
+
+/// An unsigned 256-bit integer, just wide enough for targets and work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub const fn from_u64(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+
+    pub fn from_be_bytes(bytes: &Hash) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, chunk) in bytes.chunks_exact(8).enumerate() {
+            limbs[3 - i] = u64::from_be_bytes(chunk.try_into().expect("8-byte chunk"));
+        }
+        U256(limbs)
+    }
+
+    pub fn to_be_bytes(self) -> Hash {
+        let mut bytes = [0u8; 32];
+        for (i, chunk) in bytes.chunks_exact_mut(8).enumerate() {
+            chunk.copy_from_slice(&self.0[3 - i].to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(self) -> bool {
+        self == U256::ZERO
+    }
+
+    /// The lowest 64 bits.
+    pub fn low_u64(self) -> u64 {
+        self.0[0]
+    }
+
+    /// Number of bits needed to represent the value.
+    pub fn bits(self) -> u32 {
+        (0..4)
+            .rev()
+            .find(|&i| self.0[i] != 0)
+            .map_or(0, |i| 64 * i as u32 + 64 - self.0[i].leading_zeros())
+    }
+
+    pub fn checked_add(self, other: U256) -> Option<U256> {
+        let mut limbs = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (sum, overflow_a) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, overflow_b) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = overflow_a || overflow_b;
+        }
+        (!carry).then_some(U256(limbs))
+    }
+
+    pub fn saturating_add(self, other: U256) -> U256 {
+        self.checked_add(other).unwrap_or(U256::MAX)
+    }
+
+    fn wrapping_sub(self, other: U256) -> U256 {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (difference, borrow_a) = self.0[i].overflowing_sub(other.0[i]);
+            let (difference, borrow_b) = difference.overflowing_sub(borrow as u64);
+            *limb = difference;
+            borrow = borrow_a || borrow_b;
+        }
+        U256(limbs)
+    }
+
+    pub fn checked_mul_u64(self, factor: u64) -> Option<U256> {
+        let mut limbs = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let product = u128::from(self.0[i]) * u128::from(factor) + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        (carry == 0).then_some(U256(limbs))
+    }
+
+    /// Integer division by a non-zero `divisor`.
+    pub fn div_u64(self, divisor: u64) -> U256 {
+        assert!(divisor != 0, "division by zero");
+        let mut limbs = [0u64; 4];
+        let mut remainder = 0u128;
+        for i in (0..4).rev() {
+            let current = (remainder << 64) | u128::from(self.0[i]);
+            limbs[i] = (current / u128::from(divisor)) as u64;
+            remainder = current % u128::from(divisor);
+        }
+        U256(limbs)
+    }
+
+    fn bit(self, index: u32) -> bool {
+        self.0[index as usize / 64] >> (index % 64) & 1 == 1
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Binary long division. Panics if the divisor is zero.
+impl Div for U256 {
+    type Output = U256;
+
+    fn div(self, divisor: U256) -> U256 {
+        assert!(!divisor.is_zero(), "division by zero");
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for bit in (0..self.bits()).rev() {
+            // A remainder that shifts out its top bit still exceeds the
+            // divisor, and wrapping subtraction gives the right result.
+            let carry = remainder.bit(255);
+            remainder = remainder << 1;
+            if self.bit(bit) {
+                remainder.0[0] |= 1;
+            }
+            if carry || remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.0[bit as usize / 64] |= 1 << (bit % 64);
+            }
+        }
+        quotient
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        U256(self.0.map(|limb| !limb))
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        let mut limbs = [0u64; 4];
+        let (words, bits) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs.iter_mut().enumerate().skip(words) {
+            *limb = self.0[i - words] << bits;
+            if bits > 0 && i > words {
+                *limb |= self.0[i - words - 1] >> (64 - bits);
+            }
+        }
+        U256(limbs)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        let mut limbs = [0u64; 4];
+        let (words, bits) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs
+            .iter_mut()
+            .take(4usize.saturating_sub(words))
+            .enumerate()
+        {
+            *limb = self.0[i + words] >> bits;
+            if bits > 0 && i + words + 1 < 4 {
+                *limb |= self.0[i + words + 1] << (64 - bits);
+            }
+        }
+        U256(limbs)
+    }
+}
+
+/// Lowercase hex without leading zeros, prefixed with `0x`.
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = encoding::to_hex(&self.to_be_bytes());
+        let digits = hex.trim_start_matches('0');
+        write!(f, "0x{}", if digits.is_empty() { "0" } else { digits })
+    }
+}
+
+/// The largest hash a block may have. Serialized as its compact `bits`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "u32", into = "u32")]
+pub struct Target(U256);
+
+impl Target {
+    /// Decodes compact `bits`. Returns `None` for a negative, zero,
+    /// overflowing or non-canonical encoding.
+    pub fn from_bits(bits: u32) -> Option<Target> {
+        let size = bits >> 24;
+        let mantissa = bits & 0x007f_ffff;
+        if bits & 0x0080_0000 != 0 || mantissa == 0 {
+            return None;
+        }
+        let value = if size <= 3 {
+            U256::from_u64(u64::from(mantissa >> (8 * (3 - size))))
+        } else {
+            let shift = 8 * (size - 3);
+            if U256::from_u64(u64::from(mantissa)).bits() + shift > 256 {
+                return None;
+            }
+            U256::from_u64(u64::from(mantissa)) << shift
+        };
+        let target = Target(value);
+        (!value.is_zero() && target.to_bits() == bits).then_some(target)
+    }
+
+    /// The largest target at most `value` that has a compact encoding, or
+    /// `None` if `value` is zero.
+    pub fn from_value(value: U256) -> Option<Target> {
+        Target::from_bits(compact(value))
+    }
+
+    /// The target a hex-encoded hash meets when it starts with `zeros`
+    /// zero digits, rounded down to a compact encoding.
+    pub fn from_leading_zeros(zeros: usize) -> Target {
+        let shift = 4 * zeros.clamp(1, 63) as u32;
+        Target::from_value(U256::MAX >> shift).expect("shifted target is non-zero")
+    }
+
+    pub fn to_bits(self) -> u32 {
+        compact(self.0)
+    }
+
+    pub fn value(self) -> U256 {
+        self.0
+    }
+
+    /// Whether `hash`, read as a big-endian integer, is at most the target.
+    pub fn is_met_by(self, hash: &Hash) -> bool {
+        U256::from_be_bytes(hash) <= self.0
+    }
+
+    /// `is_met_by` for a lowercase hex hash. Malformed hashes never meet it.
+    pub fn is_met_by_hex(self, hash: &str) -> bool {
+        encoding::from_hex(hash)
+            .ok()
+            .and_then(|bytes| Hash::try_from(bytes).ok())
+            .is_some_and(|hash| self.is_met_by(&hash))
+    }
+
+    /// Expected number of hashes to find a block at this target,
+    /// `2^256 / (target + 1)`.
+    pub fn work(self) -> U256 {
+        match self.0.checked_add(U256::ONE) {
+            Some(divisor) => (!self.0 / divisor).saturating_add(U256::ONE),
+            None => U256::ONE,
+        }
+    }
+}
+
+/// The compact encoding of `value`, truncated to its three most
+/// significant bytes.
+fn compact(value: U256) -> u32 {
+    let mut size = value.bits().div_ceil(8);
+    let mut mantissa = if size <= 3 {
+        value.low_u64() << (8 * (3 - size))
+    } else {
+        (value >> (8 * (size - 3))).low_u64()
+    };
+    // The top mantissa bit is a sign; keep it clear.
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    (size << 24) | mantissa as u32
+}
+
+/// The compact `bits`, as eight hex digits.
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x}", self.to_bits())
+    }
+}
+
+impl TryFrom<u32> for Target {
+    type Error = String;
+
+    fn try_from(bits: u32) -> Result<Self, Self::Error> {
+        Target::from_bits(bits).ok_or_else(|| format!("invalid compact target {:08x}", bits))
+    }
+}
+
+impl From<Target> for u32 {
+    fn from(target: Target) -> u32 {
+        target.to_bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_bits_have_one_encoding() {
+        for bits in [0x1d00ffff, 0x2000ffff, 0x207fffff, 0x01010000, 0x04123456] {
+            let target = Target::from_bits(bits).unwrap();
+            assert_eq!(target.to_bits(), bits);
+            assert_eq!(Target::from_value(target.value()), Some(target));
+        }
+        assert_eq!(Target::from_bits(0x01010000).unwrap().value(), U256::ONE);
+        assert_eq!(Target::from_leading_zeros(2).to_bits(), 0x2000ffff);
+
+        // Negative, zero, overflowing and non-canonical encodings.
+        for bits in [
+            0x1d80ffff, 0x1d000000, 0x01000001, 0x22800000, 0x23010000, 0x03000001, 0x1e0000ff,
+        ] {
+            assert_eq!(Target::from_bits(bits), None, "{:08x}", bits);
+        }
+        assert_eq!(
+            serde_json::from_str::<Target>("486604799")
+                .unwrap()
+                .to_bits(),
+            0x1d00ffff
+        );
+        assert!(serde_json::from_str::<Target>("50331649").is_err());
+    }
+
+    #[test]
+    fn from_value_rounds_down_to_an_encodable_target() {
+        assert_eq!(Target::from_value(U256::ZERO), None);
+        let value = U256::from_u64(0x1234_5678);
+        let target = Target::from_value(value).unwrap();
+        assert_eq!(target.to_bits(), 0x04123456);
+        assert_eq!(target.value(), U256::from_u64(0x1234_5600));
+        let max = Target::from_value(U256::MAX).unwrap();
+        assert!(max.value() <= U256::MAX);
+        assert_eq!(max.to_bits(), 0x2100ffff);
+    }
+
+    #[test]
+    fn work_is_the_expected_number_of_hashes() {
+        assert_eq!(
+            Target::from_bits(0x01010000).unwrap().work(),
+            U256::ONE << 255
+        );
+        assert_eq!(
+            Target::from_bits(0x207fffff).unwrap().work(),
+            U256::from_u64(2)
+        );
+        assert_eq!(Target::from_leading_zeros(1).work(), U256::from_u64(16));
+        assert_eq!(Target::from_leading_zeros(2).work(), U256::from_u64(256));
+    }
+}
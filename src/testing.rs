@@ -0,0 +1,47 @@
+//! Helpers shared by the unit tests.
+
+use crate::amount::{Amount, COIN};
+use crate::block::{Block, BlockHeader};
+use crate::consensus::{Consensus, ProofOfAuthority};
+use crate::crypto::KeyPair;
+use crate::genesis::GenesisConfig;
+use crate::transaction::{Transaction, GENESIS_ADDRESS};
+
+/// `value` whole coins.
+pub fn coins(value: u64) -> Amount {
+    Amount::from_units(value * COIN)
+}
+
+/// The default genesis, except that it gives 100 coins to `key`.
+pub fn funded_genesis(key: &KeyPair) -> GenesisConfig {
+    GenesisConfig {
+        transactions: vec![Transaction::new(
+            GENESIS_ADDRESS.to_string(),
+            key.address(),
+            coins(100),
+        )],
+        ..GenesisConfig::default()
+    }
+}
+
+/// The header of a block at `index` on `previous_hash` whose coinbase pays
+/// `miner`, signed by `key`.
+pub fn signed_header(key: &KeyPair, index: u64, previous_hash: &str, miner: &str) -> BlockHeader {
+    let engine = ProofOfAuthority {
+        validators: vec![key.address()],
+        key: Some(key.clone()),
+    };
+    let coinbase = Transaction::coinbase(miner.to_string(), Amount::ZERO, index);
+    let mut block = Block::new(index, vec![coinbase], previous_hash.to_string());
+    engine.seal(&mut block, &[]).unwrap();
+    block.header
+}
+
+/// Two blocks at `index` on the same parent with different transactions,
+/// both signed by `key`.
+pub fn double_sign(key: &KeyPair, index: u64) -> (BlockHeader, BlockHeader) {
+    (
+        signed_header(key, index, "00ab", "A"),
+        signed_header(key, index, "00ab", "B"),
+    )
+}
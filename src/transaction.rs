@@ -0,0 +1,392 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::amount::{Amount, AmountError};
+use crate::block::BlockHeader;
+use crate::crypto::{self, KeyPair};
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder};
+use crate::utxo::{TxIn, TxOut};
+
+/// Sender of block rewards. Only a block's coinbase may use it.
+pub const SYSTEM_ADDRESS: &str = "System";
+/// Sender and recipient of the genesis transaction.
+pub const GENESIS_ADDRESS: &str = "Genesis";
+/// Recipient of stake transactions, which lock `amount` of the sender's
+/// balance as stake.
+pub const STAKE_ADDRESS: &str = "Stake";
+/// Recipient of unstake transactions, which return `amount` of the sender's
+/// stake to its balance.
+pub const UNSTAKE_ADDRESS: &str = "Unstake";
+/// Recipient of slash transactions, which carry evidence of a double sign.
+pub const SLASH_ADDRESS: &str = "Slash";
+/// Pseudo-addresses that user transactions can never spend from.
+pub const RESERVED_ADDRESSES: [&str; 5] = [
+    SYSTEM_ADDRESS,
+    GENESIS_ADDRESS,
+    STAKE_ADDRESS,
+    UNSTAKE_ADDRESS,
+    SLASH_ADDRESS,
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub from: String,
+    pub to: String,
+    pub amount: Amount,
+    #[serde(default)]
+    pub fee: Amount,
+    /// Position of this transaction among everything `from` has sent,
+    /// starting at zero. For a coinbase, the height of its block.
+    #[serde(default)]
+    pub nonce: u64,
+    /// Outputs spent by a UTXO transaction. Empty in the account model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<TxIn>,
+    /// Outputs created by a UTXO transaction. Empty in the account model.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<TxOut>,
+    /// Two headers signed by the same key at the same height on the same
+    /// parent. Empty except in slash transactions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub evidence: Vec<BlockHeader>,
+    /// Hex Ed25519 public key whose address is `from`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    /// Hex signature over `signing_bytes()`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl Transaction {
+    pub fn new(from: String, to: String, amount: Amount) -> Self {
+        Self::with_fee(from, to, amount, Amount::ZERO)
+    }
+
+    /// Creates a transfer that also pays `fee` to the miner of its block.
+    pub fn with_fee(from: String, to: String, amount: Amount, fee: Amount) -> Self {
+        Transaction {
+            from,
+            to,
+            amount,
+            fee,
+            nonce: 0,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            evidence: Vec::new(),
+            public_key: None,
+            signature: None,
+        }
+    }
+
+    /// Creates a transfer from `key_pair`'s address with the given account
+    /// `nonce`, signed by it.
+    pub fn signed(key_pair: &KeyPair, to: String, amount: Amount, fee: Amount, nonce: u64) -> Self {
+        let mut transaction = Self::with_fee(key_pair.address(), to, amount, fee);
+        transaction.nonce = nonce;
+        transaction.sign(key_pair);
+        transaction
+    }
+
+    /// Creates a UTXO transaction from `key_pair`'s address spending
+    /// `inputs` into `outputs` plus `fee`, signed by it.
+    pub fn utxo(key_pair: &KeyPair, inputs: Vec<TxIn>, outputs: Vec<TxOut>, fee: Amount) -> Self {
+        let mut transaction = Self::with_fee(key_pair.address(), String::new(), Amount::ZERO, fee);
+        transaction.inputs = inputs;
+        transaction.outputs = outputs;
+        transaction.sign(key_pair);
+        transaction
+    }
+
+    /// Creates a transaction locking `amount` of `key_pair`'s balance as
+    /// stake, signed by it.
+    pub fn stake(key_pair: &KeyPair, amount: Amount, fee: Amount, nonce: u64) -> Self {
+        Self::signed(key_pair, STAKE_ADDRESS.to_string(), amount, fee, nonce)
+    }
+
+    /// Creates a transaction returning `amount` of `key_pair`'s stake to its
+    /// balance, signed by it.
+    pub fn unstake(key_pair: &KeyPair, amount: Amount, fee: Amount, nonce: u64) -> Self {
+        Self::signed(key_pair, UNSTAKE_ADDRESS.to_string(), amount, fee, nonce)
+    }
+
+    /// Creates a transaction reporting that `first` and `second` were signed
+    /// by the same proposer at the same height on the same parent, signed by
+    /// `key_pair` as the reporter. Once confirmed, the proposer's stake is
+    /// burned.
+    pub fn slash(
+        key_pair: &KeyPair,
+        first: BlockHeader,
+        second: BlockHeader,
+        fee: Amount,
+        nonce: u64,
+    ) -> Self {
+        let mut transaction = Self::with_fee(
+            key_pair.address(),
+            SLASH_ADDRESS.to_string(),
+            Amount::ZERO,
+            fee,
+        );
+        transaction.nonce = nonce;
+        transaction.evidence = vec![first, second];
+        transaction.sign(key_pair);
+        transaction
+    }
+
+    /// Attaches `key_pair`'s public key and a signature over the
+    /// transaction. `from` must be `key_pair.address()` for the result to
+    /// validate.
+    pub fn sign(&mut self, key_pair: &KeyPair) {
+        self.public_key = Some(key_pair.public_key());
+        self.signature = Some(key_pair.sign(&self.signing_bytes()));
+    }
+
+    /// The bytes a signature commits to: the canonical encoding with the
+    /// signature field left out.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.encode_unsigned(&mut encoder);
+        encoder.finish()
+    }
+
+    fn encode_unsigned(&self, encoder: &mut Encoder) {
+        encoder.put_str(&self.from);
+        encoder.put_str(&self.to);
+        self.amount.encode_to(encoder);
+        self.fee.encode_to(encoder);
+        encoder.put_u64(self.nonce);
+        encoder.put_seq(&self.inputs);
+        encoder.put_seq(&self.outputs);
+        encoder.put_seq(&self.evidence);
+        encoder.put_str(self.public_key.as_deref().unwrap_or_default());
+    }
+
+    /// Creates the block reward transaction paying `amount` to `to` in the
+    /// block at `height`. The height doubles as the nonce, so every
+    /// coinbase has a distinct txid.
+    pub fn coinbase(to: String, amount: Amount, height: u64) -> Self {
+        let mut transaction = Self::new(SYSTEM_ADDRESS.to_string(), to, amount);
+        transaction.nonce = height;
+        transaction
+    }
+
+    /// Transaction id: SHA-256 of the canonical encoding, as lowercase hex.
+    pub fn txid(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.encode());
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.from == SYSTEM_ADDRESS
+    }
+
+    /// Whether this transaction moves value through inputs and outputs
+    /// rather than `to` and `amount`.
+    pub fn is_utxo(&self) -> bool {
+        !self.inputs.is_empty() || !self.outputs.is_empty()
+    }
+
+    pub fn is_stake(&self) -> bool {
+        self.to == STAKE_ADDRESS
+    }
+
+    pub fn is_unstake(&self) -> bool {
+        self.to == UNSTAKE_ADDRESS
+    }
+
+    pub fn is_slash(&self) -> bool {
+        self.to == SLASH_ADDRESS
+    }
+
+    /// Address of the proposer a slash transaction's evidence accuses, if
+    /// it names a well-formed signer.
+    pub fn offender(&self) -> Option<String> {
+        let header = self.evidence.first()?;
+        crypto::address_from_public_key_hex(&header.signer).ok()
+    }
+
+    /// Offender and height of the double sign a slash transaction reports,
+    /// if it names a well-formed signer.
+    pub fn offence(&self) -> Option<(String, u64)> {
+        Some((self.offender()?, self.evidence.first()?.index))
+    }
+
+    /// Total debited from the sender: the transferred amount plus the fee.
+    pub fn total_cost(&self) -> Result<Amount, AmountError> {
+        self.amount.checked_add(self.fee)
+    }
+}
+
+/// `from`, `to`, `amount`, `fee`, `nonce`, `inputs`, `outputs`, `evidence`,
+/// `public_key`, `signature`. Absent keys and signatures are encoded as
+/// empty strings.
+impl Encode for Transaction {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        self.encode_unsigned(encoder);
+        encoder.put_str(self.signature.as_deref().unwrap_or_default());
+    }
+}
+
+impl Decode for Transaction {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let non_empty = |value: String| Some(value).filter(|value| !value.is_empty());
+        Ok(Transaction {
+            from: decoder.get_string()?,
+            to: decoder.get_string()?,
+            amount: Amount::decode_from(decoder)?,
+            fee: Amount::decode_from(decoder)?,
+            nonce: decoder.get_u64()?,
+            inputs: decoder.get_seq()?,
+            outputs: decoder.get_seq()?,
+            evidence: decoder.get_seq()?,
+            public_key: non_empty(decoder.get_string()?),
+            signature: non_empty(decoder.get_string()?),
+        })
+    }
+}
+
+pub fn is_reserved_address(address: &str) -> bool {
+    RESERVED_ADDRESSES.contains(&address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_hex;
+    use crate::merkle;
+    use crate::utxo::OutPoint;
+
+    fn amount(value: &str) -> Amount {
+        value.parse().unwrap()
+    }
+
+    fn key_pair() -> KeyPair {
+        KeyPair::from_secret_key(&[1u8; 32])
+    }
+
+    #[test]
+    fn unsigned_transaction_matches_vector() {
+        let tx =
+            Transaction::with_fee("Alice".into(), "Bob".into(), amount("1.5"), amount("0.001"));
+        let encoded = tx.encode();
+        assert_eq!(
+            to_hex(&encoded),
+            concat!(
+                "00000005416c69636500000003426f620000000008f0d18000000000000186a0",
+                "00000000000000000000000000000000000000000000000000000000",
+            )
+        );
+        assert_eq!(
+            tx.txid(),
+            "8f41a674a286497bf0c17dfdcf1e6a67ee3db6ba6b04d1c5c7bcca2ad808f541"
+        );
+        assert_eq!(
+            to_hex(&merkle::leaf_hash(&tx)),
+            "c848d5115feb631d859dab3275aed20fa57023fad2dccf72c384b24a860214ea"
+        );
+        assert_eq!(Transaction::decode(&encoded).unwrap(), tx);
+    }
+
+    #[test]
+    fn signed_transaction_matches_vector() {
+        let key_pair = key_pair();
+        assert_eq!(
+            key_pair.public_key(),
+            "8a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf3748801b40f6f5c"
+        );
+        assert_eq!(
+            key_pair.address(),
+            "34750f98bd59fcfc946da45aaabe933be154a4b5"
+        );
+
+        let tx = Transaction::signed(&key_pair, "Bob".into(), amount("1.5"), Amount::ZERO, 0);
+        assert_eq!(
+            to_hex(&tx.signing_bytes()),
+            concat!(
+                "0000002833343735306639386264353966636663393436646134356161616265",
+                "39333362653135346134623500000003426f620000000008f0d1800000000000",
+                "0000000000000000000000000000000000000000000000000000403861383865",
+                "3364643734303966313935666435326462326433636261356437326361363730",
+                "396266316439343132316266333734383830316234306636663563",
+            )
+        );
+        assert_eq!(
+            tx.signature.as_deref(),
+            Some(concat!(
+                "a5878a69e27d988778e07a0f76fde51e32100d89421e3da2032d8d303adc5d3e",
+                "aacb5a9987003eabd5754396cf54b194489e8050624bd150552649660782d009",
+            ))
+        );
+        let encoded = tx.encode();
+        assert_eq!(
+            to_hex(&encoded),
+            concat!(
+                "0000002833343735306639386264353966636663393436646134356161616265",
+                "39333362653135346134623500000003426f620000000008f0d1800000000000",
+                "0000000000000000000000000000000000000000000000000000403861383865",
+                "3364643734303966313935666435326462326433636261356437326361363730",
+                "3962663164393431323162663337343838303162343066366635630000008061",
+                "3538373861363965323764393838373738653037613066373666646535316533",
+                "3231303064383934323165336461323033326438643330336164633564336561",
+                "6163623561393938373030336561626435373534333936636635346231393434",
+                "38396538303530363234626431353035353236343936363037383264303039",
+            )
+        );
+        assert_eq!(
+            tx.txid(),
+            "44fbc47dd8b8c26e947854ed328c2043d0f363a35bafc3dbc1fba8fcb741784a"
+        );
+        assert_eq!(Transaction::decode(&encoded).unwrap(), tx);
+    }
+
+    #[test]
+    fn utxo_transaction_matches_vector() {
+        let key_pair = key_pair();
+        let coinbase = Transaction::coinbase("Miner1".into(), amount("100.001"), 1);
+        let previous_output = OutPoint {
+            txid: coinbase.txid(),
+            index: 0,
+        };
+        let outputs = vec![
+            TxOut {
+                address: "Bob".into(),
+                amount: amount("1.5"),
+            },
+            TxOut {
+                address: key_pair.address(),
+                amount: amount("98.5"),
+            },
+        ];
+        let tx = Transaction::utxo(
+            &key_pair,
+            vec![TxIn { previous_output }],
+            outputs,
+            amount("0.001"),
+        );
+        let encoded = tx.encode();
+        assert_eq!(
+            to_hex(&encoded),
+            concat!(
+                "0000002833343735306639386264353966636663393436646134356161616265",
+                "39333362653135346134623500000000000000000000000000000000000186a0",
+                "0000000000000000000000010000004066656133643565653138636631333036",
+                "3833626330396463643532643264616231613430636235646131336638636163",
+                "39303237663236393237613566363239000000000000000200000003426f6200",
+                "00000008f0d18000000028333437353066393862643539666366633934366461",
+                "34356161616265393333626531353461346235000000024b1b12800000000000",
+                "0000403861383865336464373430396631393566643532646232643363626135",
+                "6437326361363730396266316439343132316266333734383830316234306636",
+                "6635630000008061303934383236386137663233623134303861666437623063",
+                "3630643165663137613235333563666663363236663734346164346539356535",
+                "6434356561623964326463363933633230353738623664373764613333613664",
+                "6330666262646133303530626362326661343162643636613430646266613231",
+                "33333832333035",
+            )
+        );
+        assert_eq!(
+            tx.txid(),
+            "825e5b17548ebbf566537a5a9e02e60c0c786186cbd6e2d141aeeaed9ff57ebf"
+        );
+        assert_eq!(Transaction::decode(&encoded).unwrap(), tx);
+    }
+}
@@ -0,0 +1,354 @@
+//! Unspent transaction outputs, used by chains whose genesis selects
+//! `LedgerMode::Utxo`.
+//!
+//! A UTXO transaction spends whole outputs of earlier transactions and
+//! creates new ones. Every input must belong to the signer's address
+//! (`from`), the outputs plus `fee` must add up to exactly what the inputs
+//! hold, and `to` and `amount` stay empty. Anything left over after paying
+//! the recipients goes back to the sender as a change output.
+//!
+//! Coinbase and genesis transactions keep the account shape; each creates
+//! a single output, index 0, paying `amount` to `to`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::amount::{Amount, AmountError};
+use crate::encoding::{Decode, DecodeError, Decoder, Encode, Encoder};
+use crate::transaction::Transaction;
+
+/// A reference to output `index` of the transaction `txid`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutPoint {
+    pub txid: String,
+    pub index: u32,
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.index)
+    }
+}
+
+/// `txid`, `index`.
+impl Encode for OutPoint {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        encoder.put_str(&self.txid);
+        encoder.put_u32(self.index);
+    }
+}
+
+impl Decode for OutPoint {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(OutPoint {
+            txid: decoder.get_string()?,
+            index: decoder.get_u32()?,
+        })
+    }
+}
+
+/// An output being spent. The transaction's signature authorizes every
+/// input at once.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxIn {
+    pub previous_output: OutPoint,
+}
+
+impl Encode for TxIn {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        self.previous_output.encode_to(encoder);
+    }
+}
+
+impl Decode for TxIn {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(TxIn {
+            previous_output: OutPoint::decode_from(decoder)?,
+        })
+    }
+}
+
+/// `amount` paid to `address`, spendable once by the key behind it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TxOut {
+    pub address: String,
+    pub amount: Amount,
+}
+
+/// `address`, `amount`.
+impl Encode for TxOut {
+    fn encode_to(&self, encoder: &mut Encoder) {
+        encoder.put_str(&self.address);
+        self.amount.encode_to(encoder);
+    }
+}
+
+impl Decode for TxOut {
+    fn decode_from(decoder: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(TxOut {
+            address: decoder.get_string()?,
+            amount: Amount::decode_from(decoder)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UtxoError {
+    NoInputs,
+    NoOutputs,
+    /// A UTXO transaction sets `to` or `amount`.
+    AccountFields,
+    /// The output at `index` pays nothing.
+    ZeroOutput {
+        index: usize,
+    },
+    /// The output does not exist or has already been spent.
+    UnknownOutput(OutPoint),
+    /// The output is spent twice, within one transaction or by another
+    /// pending one.
+    DoubleSpend(OutPoint),
+    /// The output belongs to `owner`, not to the transaction's signer.
+    NotOwner {
+        outpoint: OutPoint,
+        owner: String,
+    },
+    /// The inputs hold `spent` but the outputs plus fee add up to `created`.
+    Unbalanced {
+        spent: Amount,
+        created: Amount,
+    },
+    Overflow(AmountError),
+    /// `address` holds `available` in unspent outputs but needs `required`.
+    InsufficientFunds {
+        address: String,
+        available: Amount,
+        required: Amount,
+    },
+}
+
+impl fmt::Display for UtxoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UtxoError::NoInputs => write!(f, "transaction spends no outputs"),
+            UtxoError::NoOutputs => write!(f, "transaction creates no outputs"),
+            UtxoError::AccountFields => {
+                write!(f, "UTXO transaction must leave `to` and `amount` empty")
+            }
+            UtxoError::ZeroOutput { index } => write!(f, "output {} pays nothing", index),
+            UtxoError::UnknownOutput(outpoint) => {
+                write!(f, "output {} does not exist or is already spent", outpoint)
+            }
+            UtxoError::DoubleSpend(outpoint) => write!(f, "output {} is spent twice", outpoint),
+            UtxoError::NotOwner { outpoint, owner } => {
+                write!(f, "output {} belongs to {}", outpoint, owner)
+            }
+            UtxoError::Unbalanced { spent, created } => write!(
+                f,
+                "inputs hold {} but outputs and fee total {}",
+                spent, created
+            ),
+            UtxoError::Overflow(error) => write!(f, "{}", error),
+            UtxoError::InsufficientFunds {
+                address,
+                available,
+                required,
+            } => write!(
+                f,
+                "{} has {} unspent but needs {}",
+                address, available, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UtxoError {}
+
+/// Checks the rules a UTXO transaction must satisfy regardless of chain
+/// state.
+pub fn check_shape(transaction: &Transaction) -> Result<(), UtxoError> {
+    if !transaction.to.is_empty() || !transaction.amount.is_zero() {
+        return Err(UtxoError::AccountFields);
+    }
+    if transaction.inputs.is_empty() {
+        return Err(UtxoError::NoInputs);
+    }
+    if transaction.outputs.is_empty() {
+        return Err(UtxoError::NoOutputs);
+    }
+    if let Some(index) = transaction
+        .outputs
+        .iter()
+        .position(|output| output.amount.is_zero())
+    {
+        return Err(UtxoError::ZeroOutput { index });
+    }
+    let mut seen = HashSet::new();
+    for input in &transaction.inputs {
+        if !seen.insert(&input.previous_output) {
+            return Err(UtxoError::DoubleSpend(input.previous_output.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// The outputs `transaction` creates, in index order.
+pub fn created_outputs(transaction: &Transaction) -> Vec<TxOut> {
+    if transaction.is_utxo() {
+        transaction.outputs.clone()
+    } else {
+        vec![TxOut {
+            address: transaction.to.clone(),
+            amount: transaction.amount,
+        }]
+    }
+}
+
+/// The outputs one transaction spent and created.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UtxoDelta {
+    pub spent: Vec<(OutPoint, TxOut)>,
+    pub created: Vec<(OutPoint, TxOut)>,
+}
+
+/// Every output that has been created and not yet spent.
+#[derive(Debug, Clone, Default)]
+pub struct UtxoSet {
+    outputs: HashMap<OutPoint, TxOut>,
+}
+
+impl UtxoSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&TxOut> {
+        self.outputs.get(outpoint)
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&OutPoint, &TxOut)> {
+        self.outputs.iter()
+    }
+
+    /// Unspent outputs paying `address`, sorted by outpoint so coin
+    /// selection is deterministic.
+    pub fn owned_by(&self, address: &str) -> Vec<(&OutPoint, &TxOut)> {
+        let mut owned: Vec<_> = self
+            .outputs
+            .iter()
+            .filter(|(_, output)| output.address == address)
+            .collect();
+        owned.sort_by(|a, b| a.0.cmp(b.0));
+        owned
+    }
+
+    pub fn balance(&self, address: &str) -> Amount {
+        self.owned_by(address)
+            .into_iter()
+            .fold(Amount::ZERO, |total, (_, output)| {
+                total.saturating_add(output.amount)
+            })
+    }
+
+    /// Checks that every input of `transaction` is unspent and owned by its
+    /// signer, and that inputs exactly cover outputs plus fee.
+    pub fn check(&self, transaction: &Transaction) -> Result<(), UtxoError> {
+        let mut spent = Amount::ZERO;
+        for input in &transaction.inputs {
+            let outpoint = &input.previous_output;
+            let output = self
+                .get(outpoint)
+                .ok_or_else(|| UtxoError::UnknownOutput(outpoint.clone()))?;
+            if output.address != transaction.from {
+                return Err(UtxoError::NotOwner {
+                    outpoint: outpoint.clone(),
+                    owner: output.address.clone(),
+                });
+            }
+            spent = spent
+                .checked_add(output.amount)
+                .map_err(UtxoError::Overflow)?;
+        }
+
+        let amounts = transaction.outputs.iter().map(|output| output.amount);
+        let created =
+            Amount::checked_sum(amounts.chain([transaction.fee])).map_err(UtxoError::Overflow)?;
+        if spent != created {
+            return Err(UtxoError::Unbalanced { spent, created });
+        }
+        Ok(())
+    }
+
+    /// Spends the inputs of `transaction` and adds the outputs it creates.
+    /// Zero-value outputs are not tracked. Call `check` first.
+    pub fn apply(&mut self, transaction: &Transaction) -> UtxoDelta {
+        let mut delta = UtxoDelta::default();
+        for input in &transaction.inputs {
+            if let Some(output) = self.outputs.remove(&input.previous_output) {
+                delta.spent.push((input.previous_output.clone(), output));
+            }
+        }
+        let txid = transaction.txid();
+        for (index, output) in created_outputs(transaction).into_iter().enumerate() {
+            if output.amount.is_zero() {
+                continue;
+            }
+            let outpoint = OutPoint {
+                txid: txid.clone(),
+                index: index as u32,
+            };
+            self.outputs.insert(outpoint.clone(), output.clone());
+            delta.created.push((outpoint, output));
+        }
+        delta
+    }
+
+    /// Undoes `apply`: removes the created outputs and restores the spent
+    /// ones.
+    pub fn revert(&mut self, delta: &UtxoDelta) {
+        for (outpoint, _) in &delta.created {
+            self.outputs.remove(outpoint);
+        }
+        for (outpoint, output) in &delta.spent {
+            self.outputs.insert(outpoint.clone(), output.clone());
+        }
+    }
+
+    /// Picks unspent outputs of `address`, skipping any in `exclude`, until
+    /// they cover `required`. Returns them with their total.
+    pub fn select(
+        &self,
+        address: &str,
+        required: Amount,
+        exclude: &HashSet<OutPoint>,
+    ) -> Result<(Vec<OutPoint>, Amount), UtxoError> {
+        let mut selected = Vec::new();
+        let mut total = Amount::ZERO;
+        for (outpoint, output) in self.owned_by(address) {
+            if total >= required {
+                break;
+            }
+            if exclude.contains(outpoint) {
+                continue;
+            }
+            total = total.saturating_add(output.amount);
+            selected.push(outpoint.clone());
+        }
+        if total < required || selected.is_empty() {
+            return Err(UtxoError::InsufficientFunds {
+                address: address.to_string(),
+                available: total,
+                required,
+            });
+        }
+        Ok((selected, total))
+    }
+}
@@ -0,0 +1,1112 @@
+use chrono::{DateTime, Duration, Utc};
+use std::fmt;
+
+use crate::amount::{Amount, AmountError};
+use crate::block::{Block, BlockHeader, BLOCK_VERSION};
+use crate::consensus::{Consensus, ConsensusEngine};
+use crate::crypto::{self, CryptoError};
+use crate::encoding;
+use crate::genesis::{GenesisConfig, LedgerMode};
+use crate::state::{State, UNBONDING_PERIOD};
+use crate::target::Target;
+use crate::transaction::{is_reserved_address, Transaction};
+use crate::utxo::{self, UtxoError};
+
+/// Number of preceding blocks whose median timestamp a block must exceed.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+/// Furthest, in seconds, a block's timestamp may run ahead of the clock of
+/// the node validating it.
+pub const MAX_FUTURE_DRIFT: i64 = 2 * 60 * 60;
+
+/// Why a transaction was refused admission or rejected inside a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError {
+    /// The amount is zero, or a slash transaction's is not.
+    InvalidAmount,
+    /// A coinbase carries a fee.
+    InvalidFee,
+    /// Amount plus fee does not fit in an `Amount`.
+    Overflow(AmountError),
+    /// Sender and recipient are the same address.
+    SelfTransfer,
+    /// The transaction has no public key or no signature.
+    Unsigned,
+    /// The public key does not hash to the `from` address.
+    AddressMismatch,
+    /// The public key or signature is malformed or does not verify.
+    BadSignature(CryptoError),
+    /// The sender is a reserved pseudo-address such as "System".
+    ReservedSender(String),
+    /// A coinbase pays a reserved pseudo-address such as "Stake".
+    ReservedRecipient(String),
+    /// A coinbase carries a public key or signature.
+    SignedCoinbase,
+    /// The nonce is not the sender's next one, so the transaction is a
+    /// replay, a duplicate or out of order. For a coinbase, `expected` is
+    /// the block height.
+    BadNonce { expected: u64, found: u64 },
+    /// The sender cannot cover `required` (amount plus fee).
+    InsufficientFunds {
+        address: String,
+        balance: Amount,
+        required: Amount,
+    },
+    /// An unstake of `required` exceeds the `stake` the sender has locked.
+    InsufficientStake {
+        address: String,
+        stake: Amount,
+        required: Amount,
+    },
+    /// A slash transaction's evidence is not two distinct headers signed
+    /// by the same key at the same height, or another transaction, such as
+    /// a coinbase, carries evidence.
+    InvalidEvidence,
+    /// The proposer a slash transaction accuses has no stake left to burn.
+    NothingToSlash(String),
+    /// The double sign `address` made at `height` has already been
+    /// slashed.
+    AlreadySlashed { address: String, height: u64 },
+    /// The double sign at `height` is more than `UNBONDING_PERIOD` blocks
+    /// old.
+    ExpiredEvidence { height: u64 },
+    /// The transaction does not use the chain's ledger model. Coinbases
+    /// always use the account model.
+    WrongModel(LedgerMode),
+    /// A UTXO transaction is malformed or spends outputs it cannot.
+    Utxo(UtxoError),
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::InvalidAmount => write!(f, "amount must be greater than zero"),
+            TransactionError::InvalidFee => write!(f, "coinbase must not carry a fee"),
+            TransactionError::Overflow(error) => write!(f, "{}", error),
+            TransactionError::SelfTransfer => write!(f, "sender and recipient are the same"),
+            TransactionError::Unsigned => write!(f, "transaction is not signed"),
+            TransactionError::AddressMismatch => {
+                write!(f, "public key does not match sender address")
+            }
+            TransactionError::BadSignature(error) => write!(f, "{}", error),
+            TransactionError::BadNonce { expected, found } => {
+                write!(f, "expected nonce {} but found {}", expected, found)
+            }
+            TransactionError::ReservedSender(address) => {
+                write!(f, "cannot spend from reserved address {}", address)
+            }
+            TransactionError::ReservedRecipient(address) => {
+                write!(f, "coinbase cannot pay reserved address {}", address)
+            }
+            TransactionError::SignedCoinbase => write!(f, "coinbase must not be signed"),
+            TransactionError::InsufficientFunds {
+                address,
+                balance,
+                required,
+            } => write!(f, "{} has {} but needs {}", address, balance, required),
+            TransactionError::InsufficientStake {
+                address,
+                stake,
+                required,
+            } => write!(
+                f,
+                "{} has {} staked but unstakes {}",
+                address, stake, required
+            ),
+            TransactionError::InvalidEvidence => write!(f, "invalid double-sign evidence"),
+            TransactionError::NothingToSlash(address) => {
+                write!(f, "{} has no stake to slash", address)
+            }
+            TransactionError::AlreadySlashed { address, height } => write!(
+                f,
+                "{} was already slashed for its double sign at block #{}",
+                address, height
+            ),
+            TransactionError::ExpiredEvidence { height } => {
+                write!(f, "evidence from block #{} is too old to slash", height)
+            }
+            TransactionError::WrongModel(mode) => {
+                write!(f, "transaction does not use the {} model", mode)
+            }
+            TransactionError::Utxo(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for TransactionError {}
+
+/// A single consistency violation found while validating a chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainError {
+    /// The chain has no blocks at all, not even a genesis block.
+    EmptyChain,
+    /// The block's stored `hash` does not match its recomputed hash.
+    HashMismatch {
+        index: u64,
+        stored: String,
+        computed: String,
+    },
+    /// The header's `merkle_root` does not commit to the block's transactions.
+    MerkleRootMismatch {
+        index: u64,
+        stored: String,
+        computed: String,
+    },
+    /// The header's `state_root` does not commit to the account state after
+    /// the block.
+    StateRootMismatch {
+        index: u64,
+        stored: String,
+        computed: String,
+    },
+    /// The header carries a version this node does not understand.
+    UnsupportedVersion { index: u64, version: u32 },
+    /// The header's timestamp has a fraction of a second, which its hash
+    /// does not commit to.
+    FractionalTimestamp {
+        index: u64,
+        timestamp: DateTime<Utc>,
+    },
+    /// The block's `previous_hash` does not match its predecessor's `hash`.
+    BrokenLink {
+        index: u64,
+        previous_hash: String,
+        expected: String,
+    },
+    /// The block at this position does not carry the expected height.
+    IndexGap { expected: u64, found: u64 },
+    /// The header's `bits` are not a valid compact target.
+    InvalidTarget { index: u64, bits: u32 },
+    /// The header records a target other than the one the retarget rule
+    /// requires at its height.
+    UnexpectedTarget {
+        index: u64,
+        expected: Target,
+        found: u32,
+    },
+    /// The block's hash is above the `required` target.
+    InsufficientWork { index: u64, required: Target },
+    /// The block is signed by `address`, which may not sign blocks.
+    UnauthorizedSigner { index: u64, address: String },
+    /// The block's signer or signature is malformed or does not verify.
+    BadSeal { index: u64, error: CryptoError },
+    /// The block is signed by `found`, but stake-weighted selection picks
+    /// `expected`, or nobody if no stake is locked.
+    WrongProposer {
+        index: u64,
+        expected: Option<String>,
+        found: String,
+    },
+    /// The block's timestamp is not after `median`, the median timestamp
+    /// of the blocks before it.
+    StaleTimestamp {
+        index: u64,
+        timestamp: DateTime<Utc>,
+        median: DateTime<Utc>,
+    },
+    /// The block's timestamp is after `limit`, `MAX_FUTURE_DRIFT` past the
+    /// validating node's clock.
+    FutureTimestamp {
+        index: u64,
+        timestamp: DateTime<Utc>,
+        limit: DateTime<Utc>,
+    },
+    /// Block 0 differs from the canonical genesis definition in `field`.
+    BadGenesis { field: &'static str },
+    /// The transaction at `position` in block `index` breaks a ledger rule.
+    InvalidTransaction {
+        index: u64,
+        position: usize,
+        error: TransactionError,
+    },
+    /// The block has no reward transaction.
+    MissingCoinbase { index: u64 },
+    /// The block has a second reward transaction at `position`.
+    DuplicateCoinbase { index: u64, position: usize },
+    /// The coinbase claims more than the block reward plus fees.
+    ExcessiveReward {
+        index: u64,
+        claimed: Amount,
+        allowed: Amount,
+    },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::EmptyChain => write!(f, "chain has no blocks"),
+            ChainError::HashMismatch {
+                index,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "block #{}: stored hash {} does not match computed hash {}",
+                index, stored, computed
+            ),
+            ChainError::MerkleRootMismatch {
+                index,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "block #{}: merkle root {} does not match transactions root {}",
+                index, stored, computed
+            ),
+            ChainError::StateRootMismatch {
+                index,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "block #{}: state root {} does not match account state root {}",
+                index, stored, computed
+            ),
+            ChainError::UnsupportedVersion { index, version } => {
+                write!(f, "block #{}: unsupported version {}", index, version)
+            }
+            ChainError::FractionalTimestamp { index, timestamp } => write!(
+                f,
+                "block #{}: timestamp {} is not a whole second",
+                index, timestamp
+            ),
+            ChainError::BrokenLink {
+                index,
+                previous_hash,
+                expected,
+            } => write!(
+                f,
+                "block #{}: previous_hash {} does not match predecessor hash {}",
+                index, previous_hash, expected
+            ),
+            ChainError::IndexGap { expected, found } => {
+                write!(f, "expected block #{} but found block #{}", expected, found)
+            }
+            ChainError::InvalidTarget { index, bits } => {
+                write!(
+                    f,
+                    "block #{}: bits {:08x} are not a valid target",
+                    index, bits
+                )
+            }
+            ChainError::UnexpectedTarget {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "block #{}: bits {:08x} do not match retargeted bits {}",
+                index, found, expected
+            ),
+            ChainError::InsufficientWork { index, required } => write!(
+                f,
+                "block #{}: hash does not meet target {}",
+                index, required
+            ),
+            ChainError::UnauthorizedSigner { index, address } => {
+                write!(f, "block #{}: signer {} is not a validator", index, address)
+            }
+            ChainError::BadSeal { index, error } => {
+                write!(f, "block #{}: bad block signature: {}", index, error)
+            }
+            ChainError::WrongProposer {
+                index,
+                expected: Some(expected),
+                found,
+            } => write!(
+                f,
+                "block #{}: signed by {} but the selected proposer is {}",
+                index, found, expected
+            ),
+            ChainError::WrongProposer {
+                index,
+                expected: None,
+                found,
+            } => write!(
+                f,
+                "block #{}: signed by {} but no stake is locked",
+                index, found
+            ),
+            ChainError::StaleTimestamp {
+                index,
+                timestamp,
+                median,
+            } => write!(
+                f,
+                "block #{}: timestamp {} is not after the median {} of recent blocks",
+                index, timestamp, median
+            ),
+            ChainError::FutureTimestamp {
+                index,
+                timestamp,
+                limit,
+            } => write!(
+                f,
+                "block #{}: timestamp {} is after {}, the furthest allowed ahead",
+                index, timestamp, limit
+            ),
+            ChainError::BadGenesis { field } => {
+                write!(f, "genesis block does not match canonical {}", field)
+            }
+            ChainError::InvalidTransaction {
+                index,
+                position,
+                error,
+            } => write!(f, "block #{}: transaction {}: {}", index, position, error),
+            ChainError::MissingCoinbase { index } => {
+                write!(f, "block #{}: missing coinbase transaction", index)
+            }
+            ChainError::DuplicateCoinbase { index, position } => write!(
+                f,
+                "block #{}: transaction {} is a second coinbase",
+                index, position
+            ),
+            ChainError::ExcessiveReward {
+                index,
+                claimed,
+                allowed,
+            } => write!(
+                f,
+                "block #{}: coinbase claims {} but at most {} is allowed",
+                index, claimed, allowed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+/// Checks the rules a user transaction must satisfy regardless of chain
+/// state: a non-zero amount and distinct endpoints (or, for a UTXO
+/// transaction, a well-formed set of inputs and outputs, and for a slash,
+/// a zero amount and valid evidence), a non-reserved sender and a valid
+/// signature by the key behind `from`.
+pub fn check_transaction(transaction: &Transaction) -> Result<(), TransactionError> {
+    if is_reserved_address(&transaction.from) {
+        return Err(TransactionError::ReservedSender(transaction.from.clone()));
+    }
+    if transaction.is_slash() {
+        if !transaction.amount.is_zero() {
+            return Err(TransactionError::InvalidAmount);
+        }
+        check_evidence(&transaction.evidence)?;
+    } else if !transaction.evidence.is_empty() {
+        return Err(TransactionError::InvalidEvidence);
+    } else if transaction.is_utxo() {
+        utxo::check_shape(transaction).map_err(TransactionError::Utxo)?;
+    } else {
+        if transaction.amount.is_zero() {
+            return Err(TransactionError::InvalidAmount);
+        }
+        if transaction.from == transaction.to {
+            return Err(TransactionError::SelfTransfer);
+        }
+    }
+    check_signature(transaction)
+}
+
+/// Checks that `evidence` proves a double sign: two headers after genesis
+/// for the same slot, at the same height on the same parent, whose signed
+/// contents differ, both validly signed by the same key. Signers are
+/// compared as key bytes, and keys and signatures must be canonical hex, so
+/// respelling one block's seal proves nothing. A proposal made again on a
+/// new parent after a reorganization is not a double sign.
+pub fn check_evidence(evidence: &[BlockHeader]) -> Result<(), TransactionError> {
+    let [first, second] = evidence else {
+        return Err(TransactionError::InvalidEvidence);
+    };
+    let signers = (
+        encoding::from_hex(&first.signer),
+        encoding::from_hex(&second.signer),
+    );
+    let (Ok(first_signer), Ok(second_signer)) = signers else {
+        return Err(TransactionError::InvalidEvidence);
+    };
+    if first.index == 0
+        || first.index != second.index
+        || first.previous_hash != second.previous_hash
+        || first_signer != second_signer
+        || first.signing_bytes() == second.signing_bytes()
+    {
+        return Err(TransactionError::InvalidEvidence);
+    }
+    for header in evidence {
+        crypto::verify_signature(&header.signer, &header.signing_bytes(), &header.signature)
+            .map_err(|_| TransactionError::InvalidEvidence)?;
+    }
+    Ok(())
+}
+
+/// Checks that `transaction` uses the ledger model `mode`.
+pub fn check_model(transaction: &Transaction, mode: LedgerMode) -> Result<(), TransactionError> {
+    if transaction.is_utxo() != (mode == LedgerMode::Utxo) {
+        return Err(TransactionError::WrongModel(mode));
+    }
+    Ok(())
+}
+
+/// Checks that `transaction` is signed by the key that owns `from`.
+pub fn check_signature(transaction: &Transaction) -> Result<(), TransactionError> {
+    let (Some(public_key), Some(signature)) = (&transaction.public_key, &transaction.signature)
+    else {
+        return Err(TransactionError::Unsigned);
+    };
+    let address =
+        crypto::address_from_public_key_hex(public_key).map_err(TransactionError::BadSignature)?;
+    if address != transaction.from {
+        return Err(TransactionError::AddressMismatch);
+    }
+    crypto::verify_signature(public_key, &transaction.signing_bytes(), signature)
+        .map_err(TransactionError::BadSignature)
+}
+
+/// Checks that `transaction` carries its sender's next nonce.
+pub fn check_nonce(transaction: &Transaction, expected: u64) -> Result<(), TransactionError> {
+    if transaction.nonce != expected {
+        return Err(TransactionError::BadNonce {
+            expected,
+            found: transaction.nonce,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that a sender holding `balance` can pay for `transaction`.
+pub fn check_spend(transaction: &Transaction, balance: Amount) -> Result<(), TransactionError> {
+    let required = transaction
+        .total_cost()
+        .map_err(TransactionError::Overflow)?;
+    if balance < required {
+        return Err(TransactionError::InsufficientFunds {
+            address: transaction.from.clone(),
+            balance,
+            required,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that a sender with `stake` locked and `balance` free can unstake
+/// `transaction.amount` and pay its fee.
+pub fn check_unstake(
+    transaction: &Transaction,
+    stake: Amount,
+    balance: Amount,
+) -> Result<(), TransactionError> {
+    if stake < transaction.amount {
+        return Err(TransactionError::InsufficientStake {
+            address: transaction.from.clone(),
+            stake,
+            required: transaction.amount,
+        });
+    }
+    if balance < transaction.fee {
+        return Err(TransactionError::InsufficientFunds {
+            address: transaction.from.clone(),
+            balance,
+            required: transaction.fee,
+        });
+    }
+    Ok(())
+}
+
+/// Checks a slash `transaction` for the block at `height`: its evidence
+/// must be at most `UNBONDING_PERIOD` blocks old, its offence must not be
+/// `already_slashed`, and the accused proposer must still have `slashable`
+/// stake to burn.
+pub fn check_slash(
+    transaction: &Transaction,
+    slashable: Amount,
+    already_slashed: bool,
+    height: u64,
+) -> Result<(), TransactionError> {
+    let (offender, offence) = transaction.offence().unwrap_or_default();
+    if offence.saturating_add(UNBONDING_PERIOD) < height {
+        return Err(TransactionError::ExpiredEvidence { height: offence });
+    }
+    if already_slashed {
+        return Err(TransactionError::AlreadySlashed {
+            address: offender,
+            height: offence,
+        });
+    }
+    if slashable.is_zero() {
+        return Err(TransactionError::NothingToSlash(offender));
+    }
+    Ok(())
+}
+
+/// Returns every violation in a lone header at height `expected_index`.
+/// `previous_hash` is the hash of the header before it, or `None` for the
+/// genesis header.
+///
+/// These are the checks a light client can make without transactions or
+/// knowing the consensus engine: height, version, a whole-second
+/// timestamp, linkage and, for height 0, the canonical genesis fields.
+/// `check_seal` covers the rest.
+pub fn validate_header(
+    header: &BlockHeader,
+    expected_index: u64,
+    previous_hash: Option<&str>,
+    genesis: &GenesisConfig,
+) -> Vec<ChainError> {
+    let mut errors = Vec::new();
+
+    if header.index != expected_index {
+        errors.push(ChainError::IndexGap {
+            expected: expected_index,
+            found: header.index,
+        });
+    }
+
+    if header.version != BLOCK_VERSION {
+        errors.push(ChainError::UnsupportedVersion {
+            index: header.index,
+            version: header.version,
+        });
+    }
+
+    if header.timestamp.timestamp_subsec_nanos() != 0 {
+        errors.push(ChainError::FractionalTimestamp {
+            index: header.index,
+            timestamp: header.timestamp,
+        });
+    }
+
+    match previous_hash {
+        Some(expected) if header.previous_hash != expected => {
+            errors.push(ChainError::BrokenLink {
+                index: header.index,
+                previous_hash: header.previous_hash.clone(),
+                expected: expected.to_string(),
+            });
+        }
+        Some(_) => {}
+        None => check_genesis_header(header, genesis, &mut errors),
+    }
+
+    errors
+}
+
+/// Median timestamp of the last `MEDIAN_TIME_SPAN` headers of `previous`,
+/// taking the later of the middle two for an even count, or `None` if
+/// `previous` is empty.
+pub fn median_time_past<H: AsRef<BlockHeader>>(previous: &[H]) -> Option<DateTime<Utc>> {
+    let recent = &previous[previous.len().saturating_sub(MEDIAN_TIME_SPAN)..];
+    let mut timestamps: Vec<_> = recent
+        .iter()
+        .map(|header| header.as_ref().timestamp)
+        .collect();
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied()
+}
+
+/// Checks that `header`, whose predecessors are `previous`, is timestamped
+/// after their `median_time_past` and no more than `MAX_FUTURE_DRIFT`
+/// seconds after `now`. The genesis header is fixed by its config and
+/// exempt.
+pub fn check_timestamp<H: AsRef<BlockHeader>>(
+    header: &BlockHeader,
+    previous: &[H],
+    now: DateTime<Utc>,
+) -> Result<(), ChainError> {
+    let Some(median) = median_time_past(previous) else {
+        return Ok(());
+    };
+    if header.timestamp <= median {
+        return Err(ChainError::StaleTimestamp {
+            index: header.index,
+            timestamp: header.timestamp,
+            median,
+        });
+    }
+    let limit = now + Duration::seconds(MAX_FUTURE_DRIFT);
+    if header.timestamp > limit {
+        return Err(ChainError::FutureTimestamp {
+            index: header.index,
+            timestamp: header.timestamp,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+/// Checks the seal of `header`, whose hash is `hash` and whose
+/// predecessors are `previous`, with the consensus engine `genesis`
+/// selects.
+pub fn check_seal(
+    header: &BlockHeader,
+    hash: &str,
+    previous: &[BlockHeader],
+    genesis: &GenesisConfig,
+) -> Result<(), ChainError> {
+    genesis.engine().verify_seal(header, hash, previous)
+}
+
+/// Returns every violation in `chain`, in block order. An empty result
+/// means the chain is valid.
+///
+/// Block 0 must match `genesis` exactly, and every block must carry a seal
+/// the genesis consensus engine accepts. Under proof of work, every block's
+/// hash must meet both its own recorded target and the genesis target, and
+/// if the genesis sets a retarget rule, the recorded target must be the one
+/// it requires; under proof of stake, every block must be signed by the
+/// proposer its parent's state selects. Every later block must carry
+/// exactly one coinbase worth at most the genesis `mining_reward` plus its
+/// fees. In the account model no transaction may spend more than its sender
+/// holds at that point in the chain, and each sender's nonces must count up
+/// from zero; in the UTXO model every input must be an unspent output of
+/// its signer. Each header's `state_root` must match the account state
+/// after its block.
+pub fn validate_all(chain: &[Block], genesis: &GenesisConfig) -> Vec<ChainError> {
+    let mut validator = ChainValidator::new(genesis);
+    for block in chain {
+        validator.push(block);
+    }
+    validator.finish()
+}
+
+/// `validate_all` one block at a time, from genesis up, holding only the
+/// headers and ledger state so far, so a chain can be checked as it is read
+/// from storage.
+///
+/// The ledger replay applies valid transactions only, rejecting overspends,
+/// replays, double spends and forged or inflated rewards, so later state
+/// reflects only what the rules allowed.
+pub struct ChainValidator<'a> {
+    genesis: &'a GenesisConfig,
+    engine: ConsensusEngine,
+    headers: Vec<BlockHeader>,
+    previous_hash: Option<String>,
+    state: State,
+    errors: Vec<ChainError>,
+}
+
+impl<'a> ChainValidator<'a> {
+    pub fn new(genesis: &'a GenesisConfig) -> Self {
+        ChainValidator {
+            genesis,
+            engine: genesis.engine(),
+            headers: Vec::new(),
+            previous_hash: None,
+            state: State::new(genesis.ledger),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Checks `block` as the next block of the chain.
+    pub fn push(&mut self, block: &Block) {
+        let genesis = self.genesis;
+        if self.headers.is_empty() && block.transactions != genesis.transactions {
+            self.errors.push(ChainError::BadGenesis {
+                field: "transactions",
+            });
+        }
+        let previous_hash = self.previous_hash.as_deref();
+        self.errors
+            .extend(validate_block(block, &self.headers, previous_hash, genesis));
+
+        if self.headers.is_empty() {
+            // The genesis block is canonical, so its allocations are applied as-is.
+            self.state.apply_block(block);
+        } else {
+            self.state.begin_block();
+            check_block_ledger(
+                block,
+                genesis.mining_reward,
+                &mut self.state,
+                &self.engine,
+                &mut self.errors,
+            );
+        }
+        self.headers.push(block.header.clone());
+        self.previous_hash = Some(block.hash.clone());
+    }
+
+    /// Every violation found, in block order. An empty result means the
+    /// blocks pushed form a valid chain.
+    pub fn finish(self) -> Vec<ChainError> {
+        if self.headers.is_empty() {
+            return vec![ChainError::EmptyChain];
+        }
+        self.errors
+    }
+
+    /// The ledger state after the blocks pushed, or the first violation
+    /// found if they do not form a valid chain.
+    pub fn into_state(self) -> Result<State, ChainError> {
+        if self.headers.is_empty() {
+            return Err(ChainError::EmptyChain);
+        }
+        match self.errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(self.state),
+        }
+    }
+}
+
+/// Returns every violation in `block` that does not depend on the ledger:
+/// its header and timestamp checked against `previous`, the headers from
+/// genesis up to its parent whose hash is `previous_hash`, its stored hash
+/// and its Merkle root.
+pub fn validate_block(
+    block: &Block,
+    previous: &[BlockHeader],
+    previous_hash: Option<&str>,
+    genesis: &GenesisConfig,
+) -> Vec<ChainError> {
+    let mut errors = validate_header(&block.header, previous.len() as u64, previous_hash, genesis);
+    if let Err(error) = check_timestamp(&block.header, previous, Utc::now()) {
+        errors.push(error);
+    }
+    if let Err(error) = check_seal(&block.header, &block.hash, previous, genesis) {
+        errors.push(error);
+    }
+
+    let computed = block.calculate_hash();
+    if block.hash != computed {
+        errors.push(ChainError::HashMismatch {
+            index: block.header.index,
+            stored: block.hash.clone(),
+            computed,
+        });
+    }
+
+    let computed_root = block.calculate_merkle_root();
+    if block.header.merkle_root != computed_root {
+        errors.push(ChainError::MerkleRootMismatch {
+            index: block.header.index,
+            stored: block.header.merkle_root.clone(),
+            computed: computed_root,
+        });
+    }
+
+    errors
+}
+
+/// Applies `block` on top of `state` if its proposer, transactions and
+/// state root are valid there, so it can later be reverted with
+/// `revert_block`. Otherwise leaves `state` unchanged and returns the first
+/// violation.
+pub fn connect_block(
+    block: &Block,
+    state: &mut State,
+    genesis: &GenesisConfig,
+) -> Result<(), ChainError> {
+    let mut errors = Vec::new();
+    state.begin_block();
+    let engine = genesis.engine();
+    check_block_ledger(block, genesis.mining_reward, state, &engine, &mut errors);
+    match errors.into_iter().next() {
+        Some(error) => {
+            state.revert_block();
+            Err(error)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Checks the proposer of one non-genesis block against `state` and
+/// applies its valid transactions, reporting the invalid ones and a state
+/// root mismatch.
+fn check_block_ledger(
+    block: &Block,
+    mining_reward: Amount,
+    state: &mut State,
+    engine: &ConsensusEngine,
+    errors: &mut Vec<ChainError>,
+) {
+    if let Err(error) = engine.verify_state(&block.header, state) {
+        errors.push(error);
+    }
+    let allowed = block_reward(mining_reward, &block.transactions);
+    let mut coinbase_seen = false;
+
+    for (position, transaction) in block.transactions.iter().enumerate() {
+        let result = if transaction.is_coinbase() {
+            if coinbase_seen {
+                errors.push(ChainError::DuplicateCoinbase {
+                    index: block.header.index,
+                    position,
+                });
+                continue;
+            }
+            coinbase_seen = true;
+            check_coinbase(transaction, block.header.index, position, allowed)
+        } else {
+            check_transaction(transaction)
+                .and_then(|_| state.check(transaction))
+                .map_err(|error| ChainError::InvalidTransaction {
+                    index: block.header.index,
+                    position,
+                    error,
+                })
+        };
+
+        match result {
+            Ok(()) => state.apply_transaction(transaction),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    if !coinbase_seen {
+        errors.push(ChainError::MissingCoinbase {
+            index: block.header.index,
+        });
+    }
+
+    let computed = encoding::to_hex(&state.root());
+    if block.header.state_root != computed {
+        errors.push(ChainError::StateRootMismatch {
+            index: block.header.index,
+            stored: block.header.state_root.clone(),
+            computed,
+        });
+    }
+}
+
+/// The most a block's coinbase may claim: `mining_reward` plus the fees of
+/// every other transaction in `transactions`.
+pub fn block_reward(mining_reward: Amount, transactions: &[Transaction]) -> Amount {
+    transactions
+        .iter()
+        .filter(|transaction| !transaction.is_coinbase())
+        .fold(mining_reward, |total, transaction| {
+            total.saturating_add(transaction.fee)
+        })
+}
+
+/// Checks the coinbase at `position` in block `index`: an unsigned account
+/// payment without fee or evidence to an ordinary address, carrying the
+/// block height as its nonce and claiming at most `allowed`.
+fn check_coinbase(
+    transaction: &Transaction,
+    index: u64,
+    position: usize,
+    allowed: Amount,
+) -> Result<(), ChainError> {
+    let invalid = |error| ChainError::InvalidTransaction {
+        index,
+        position,
+        error,
+    };
+    check_model(transaction, LedgerMode::Account).map_err(invalid)?;
+    if !transaction.fee.is_zero() {
+        return Err(invalid(TransactionError::InvalidFee));
+    }
+    if is_reserved_address(&transaction.to) {
+        let recipient = transaction.to.clone();
+        return Err(invalid(TransactionError::ReservedRecipient(recipient)));
+    }
+    if !transaction.evidence.is_empty() {
+        return Err(invalid(TransactionError::InvalidEvidence));
+    }
+    if transaction.public_key.is_some() || transaction.signature.is_some() {
+        return Err(invalid(TransactionError::SignedCoinbase));
+    }
+    check_nonce(transaction, index).map_err(invalid)?;
+    if transaction.amount > allowed {
+        return Err(ChainError::ExcessiveReward {
+            index,
+            claimed: transaction.amount,
+            allowed,
+        });
+    }
+    Ok(())
+}
+
+fn check_genesis_header(
+    header: &BlockHeader,
+    genesis: &GenesisConfig,
+    errors: &mut Vec<ChainError>,
+) {
+    if header.timestamp != genesis.timestamp {
+        errors.push(ChainError::BadGenesis { field: "timestamp" });
+    }
+    if header.merkle_root != genesis.merkle_root() {
+        errors.push(ChainError::BadGenesis {
+            field: "merkle_root",
+        });
+    }
+    if header.state_root != genesis.state_root() {
+        errors.push(ChainError::BadGenesis {
+            field: "state_root",
+        });
+    }
+    if header.previous_hash != genesis.previous_hash {
+        errors.push(ChainError::BadGenesis {
+            field: "previous_hash",
+        });
+    }
+}
+
+/// Validates `chain`, stopping at the first violation.
+pub fn validate(chain: &[Block], genesis: &GenesisConfig) -> Result<(), ChainError> {
+    match validate_all(chain, genesis).into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
+
+pub fn is_chain_valid(chain: &[Block], genesis: &GenesisConfig) -> bool {
+    validate(chain, genesis).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amount::COIN;
+    use crate::chain::Blockchain;
+    use crate::crypto::KeyPair;
+    use crate::testing::{double_sign, signed_header};
+
+    #[test]
+    fn transactions_must_be_signed_by_the_sender_key() {
+        let key = KeyPair::from_secret_key(&[1u8; 32]);
+        let other = KeyPair::from_secret_key(&[2u8; 32]);
+        let signed =
+            Transaction::signed(&key, "Bob".into(), Amount::from_units(1), Amount::ZERO, 0);
+        assert_eq!(check_signature(&signed), Ok(()));
+
+        let mut unsigned = signed.clone();
+        unsigned.signature = None;
+        assert_eq!(check_signature(&unsigned), Err(TransactionError::Unsigned));
+
+        let mut wrong_key = signed.clone();
+        wrong_key.public_key = Some(other.public_key());
+        assert_eq!(
+            check_signature(&wrong_key),
+            Err(TransactionError::AddressMismatch)
+        );
+
+        let mut tampered = signed.clone();
+        tampered.amount = Amount::from_units(2);
+        assert_eq!(
+            check_signature(&tampered),
+            Err(TransactionError::BadSignature(CryptoError::BadSignature))
+        );
+
+        let mut resigned = tampered.clone();
+        resigned.sign(&other);
+        assert_eq!(
+            check_signature(&resigned),
+            Err(TransactionError::AddressMismatch)
+        );
+    }
+
+    #[test]
+    fn spends_whose_cost_overflows_are_refused() {
+        let key = KeyPair::from_secret_key(&[1u8; 32]);
+        let transaction =
+            Transaction::signed(&key, "Bob".into(), Amount::MAX, Amount::from_units(1), 0);
+        assert_eq!(
+            check_spend(&transaction, Amount::MAX),
+            Err(TransactionError::Overflow(AmountError::Overflow))
+        );
+        let mut chain = Blockchain::new();
+        assert_eq!(
+            chain.add_transaction(transaction),
+            Err(TransactionError::Overflow(AmountError::Overflow))
+        );
+    }
+
+    #[test]
+    fn validate_all_reports_every_violation() {
+        let mut chain = Blockchain::new();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        chain.mine_pending_transactions("Miner".into()).unwrap();
+        let genesis = chain.genesis.clone();
+        let mut blocks = chain.get_blocks(0..3).unwrap();
+        assert_eq!(validate_all(&blocks, &genesis), Vec::new());
+
+        blocks[1].transactions[0].amount = Amount::from_units(1000 * COIN);
+        blocks[2].header.previous_hash = "00ff".into();
+        let errors = validate_all(&blocks, &genesis);
+        let found = |expected: fn(&ChainError) -> bool| errors.iter().any(expected);
+        assert!(found(|e| matches!(
+            e,
+            ChainError::MerkleRootMismatch { index: 1, .. }
+        )));
+        assert!(found(|e| matches!(
+            e,
+            ChainError::StateRootMismatch { index: 1, .. }
+        )));
+        assert!(found(|e| matches!(
+            e,
+            ChainError::ExcessiveReward { index: 1, .. }
+        )));
+        assert!(found(|e| matches!(
+            e,
+            ChainError::BrokenLink { index: 2, .. }
+        )));
+        assert!(found(|e| matches!(
+            e,
+            ChainError::HashMismatch { index: 2, .. }
+        )));
+        assert_eq!(validate(&blocks, &genesis), Err(errors[0].clone()));
+    }
+
+    #[test]
+    fn evidence_must_be_two_signed_blocks_at_one_height() {
+        let key = KeyPair::from_secret_key(&[1u8; 32]);
+        let (first, second) = double_sign(&key, 3);
+        assert_eq!(check_evidence(&[first.clone(), second.clone()]), Ok(()));
+
+        // The same block twice, even with its signer respelled.
+        let mut respelled = first.clone();
+        respelled.signer = respelled.signer.to_uppercase();
+        assert_eq!(
+            check_evidence(&[first.clone(), first.clone()]),
+            Err(TransactionError::InvalidEvidence)
+        );
+        assert_eq!(
+            check_evidence(&[first.clone(), respelled]),
+            Err(TransactionError::InvalidEvidence)
+        );
+
+        let other = KeyPair::from_secret_key(&[2u8; 32]);
+        let (_, by_other) = double_sign(&other, 3);
+        let (_, later) = double_sign(&key, 4);
+        // A proposal made again on a new parent after a reorganization.
+        let reproposed = signed_header(&key, 3, "00cd", "A");
+        let mut forged = second.clone();
+        forged.nonce += 1;
+        for evidence in [
+            [first.clone(), by_other],
+            [first.clone(), later],
+            [first.clone(), reproposed],
+            [first, forged],
+        ] {
+            assert_eq!(
+                check_evidence(&evidence),
+                Err(TransactionError::InvalidEvidence)
+            );
+        }
+    }
+
+    #[test]
+    fn slash_needs_recent_unslashed_offence_and_stake() {
+        let key = KeyPair::from_secret_key(&[1u8; 32]);
+        let reporter = KeyPair::from_secret_key(&[2u8; 32]);
+        let (first, second) = double_sign(&key, 3);
+        let slash = Transaction::slash(&reporter, first, second, Amount::ZERO, 0);
+        let stake = Amount::from_units(COIN);
+        let last = 3 + UNBONDING_PERIOD;
+
+        assert_eq!(check_slash(&slash, stake, false, last), Ok(()));
+        assert_eq!(
+            check_slash(&slash, stake, false, last + 1),
+            Err(TransactionError::ExpiredEvidence { height: 3 })
+        );
+        assert_eq!(
+            check_slash(&slash, stake, true, last),
+            Err(TransactionError::AlreadySlashed {
+                address: key.address(),
+                height: 3,
+            })
+        );
+        assert_eq!(
+            check_slash(&slash, Amount::ZERO, false, last),
+            Err(TransactionError::NothingToSlash(key.address()))
+        );
+    }
+}